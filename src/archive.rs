@@ -0,0 +1,89 @@
+//! Reading SVD from compressed files and archives.
+//!
+//! `.gz` and `.xz` files are decompressed transparently. Zip archives (also CMSIS-Pack
+//! `.pack`) contain many files, so path of SVD inside archive is needed.
+
+use anyhow::{anyhow, bail, Context};
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::Path,
+};
+
+/// File is zip archive or CMSIS-Pack
+pub fn is_archive(path: &Path) -> bool {
+    matches!(extension(path).as_deref(), Some("zip") | Some("pack"))
+}
+
+/// Paths of SVD files inside archive
+pub fn svd_entries(archive: &Path) -> anyhow::Result<Vec<String>> {
+    let zip = open_zip(archive)?;
+    let mut entries: Vec<String> = zip
+        .file_names()
+        .filter(|name| name.to_lowercase().ends_with(".svd"))
+        .map(String::from)
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+/// SVD inside archive if there is only one
+pub fn single_svd_entry(archive: &Path) -> anyhow::Result<String> {
+    let mut entries = svd_entries(archive)?;
+    match entries.len() {
+        1 => Ok(entries.remove(0)),
+        0 => bail!("{} doesn't contain SVD files", archive.display()),
+        n => bail!(
+            "{} contains {} SVD files, choose one of:\n{}",
+            archive.display(),
+            n,
+            entries.join("\n")
+        ),
+    }
+}
+
+/// Read SVD text from plain, `.gz` or `.xz` file, or from file `inner` inside archive
+pub fn read_svd(path: &Path, inner: Option<&str>) -> anyhow::Result<String> {
+    let read = || -> anyhow::Result<String> {
+        let mut text = String::new();
+        match (inner, extension(path).as_deref()) {
+            (Some(inner), _) => {
+                let mut zip = open_zip(path)?;
+                let mut file = zip
+                    .by_name(inner)
+                    .with_context(|| format!("{} not found", inner))?;
+                file.read_to_string(&mut text)?;
+            }
+            (None, _) if is_archive(path) => bail!("path of SVD inside archive is not set"),
+            (None, Some("gz")) => {
+                flate2::read::GzDecoder::new(File::open(path)?).read_to_string(&mut text)?;
+            }
+            (None, Some("xz")) => {
+                let mut data = Vec::new();
+                lzma_rs::xz_decompress(&mut BufReader::new(File::open(path)?), &mut data)
+                    .map_err(|e| anyhow!("{:?}", e))?;
+                text = String::from_utf8(data)?;
+            }
+            (None, _) => text = fs::read_to_string(path)?,
+        }
+        Ok(text)
+    };
+    read().with_context(|| format!("Unable to read {}", display_name(path, inner)))
+}
+
+/// `archive!inner` for SVD inside archive, path otherwise
+pub fn display_name(path: &Path, inner: Option<&str>) -> String {
+    match inner {
+        Some(inner) => format!("{}!{}", path.display(), inner),
+        None => path.display().to_string(),
+    }
+}
+
+fn open_zip(path: &Path) -> anyhow::Result<zip::ZipArchive<File>> {
+    let file = File::open(path).with_context(|| format!("Unable to read {}", path.display()))?;
+    zip::ZipArchive::new(file).with_context(|| format!("{} is not zip archive", path.display()))
+}
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_lowercase())
+}
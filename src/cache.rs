@@ -0,0 +1,136 @@
+//! Disk cache of resolved devices.
+//!
+//! Parsing and resolving big SVD takes seconds, so resolved [`Device`] is saved in
+//! `$XDG_CACHE_HOME/svdselector` (`~/.cache/svdselector`) as JSON. Cache file is used
+//! while SVD has same path and modification time, or same content hash if it was touched.
+
+use crate::{archive, Device};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use std::path::{Path, PathBuf};
+use std::{env, fs, time::SystemTime};
+
+/// Format of cache file. Must be increased when [`Device`] or the way SVD is resolved
+/// into it changes, so cache written by older code isn't used
+const FORMAT: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    /// [`FORMAT`] of cache
+    format: u32,
+    /// Version of `svdselector` which wrote cache. Other versions can have other tree
+    version: String,
+    svd_path: PathBuf,
+    /// Path inside archive
+    inner: Option<String>,
+    modified: SystemTime,
+    hash: u64,
+    device: Device,
+}
+
+/// Load device from cache or parse SVD and update cache. Cache errors are not fatal:
+/// error of writing cache is returned with device for caller to report
+pub fn load(
+    svd_path: &Path,
+    inner: Option<&str>,
+) -> anyhow::Result<(Device, Option<anyhow::Error>)> {
+    let svd_path = &svd_path
+        .canonicalize()
+        .with_context(|| format!("Unable to read {}", svd_path.display()))?;
+    let cache_path = match cache_path(svd_path, inner) {
+        Some(path) => path,
+        None => return Ok((Device::load(svd_path, inner)?, None)),
+    };
+    let modified = fs::metadata(svd_path)?.modified()?;
+    let cached: Option<CacheFile> = fs::read(&cache_path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .filter(|c: &CacheFile| {
+            c.format == FORMAT
+                && c.version == env!("CARGO_PKG_VERSION")
+                && &c.svd_path == svd_path
+                && c.inner.as_deref() == inner
+        });
+    if let Some(cached) = &cached {
+        if cached.modified == modified {
+            return Ok((cached.device.clone(), None));
+        }
+    }
+    let xml = archive::read_svd(svd_path, inner)?;
+    let hash = fnv1a(&[xml.as_bytes()]);
+    let device = match cached {
+        // Only modification time changed (e.g. after checkout)
+        Some(cached) if cached.hash == hash => cached.device,
+        _ => Device::parse(&xml).with_context(|| {
+            format!("Unable to parse {}", archive::display_name(svd_path, inner))
+        })?,
+    };
+    let cache = CacheFile {
+        format: FORMAT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        svd_path: svd_path.clone(),
+        inner: inner.map(String::from),
+        modified,
+        hash,
+        device,
+    };
+    let error = write(&cache_path, &cache)
+        .with_context(|| format!("Unable to write cache {}", cache_path.display()))
+        .err();
+    Ok((cache.device, error))
+}
+
+fn write(cache_path: &Path, cache: &CacheFile) -> anyhow::Result<()> {
+    if let Some(dir) = cache_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Write whole file before replacing old one, so other instance never reads half of it
+    let tmp = cache_path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(cache)?)?;
+    fs::rename(&tmp, cache_path)?;
+    Ok(())
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base = match (env::var_os("XDG_CACHE_HOME"), env::var_os("HOME")) {
+        (Some(dir), _) if !dir.is_empty() => PathBuf::from(dir),
+        (_, Some(home)) => Path::new(&home).join(".cache"),
+        _ => PathBuf::from(env::var_os("LOCALAPPDATA")?),
+    };
+    Some(base.join("svdselector"))
+}
+
+/// Cache file of SVD, one for every SVD path
+fn cache_path(svd_path: &Path, inner: Option<&str>) -> Option<PathBuf> {
+    let path = svd_path.to_string_lossy();
+    let key = match inner {
+        Some(inner) => fnv1a(&[path.as_bytes(), b"\0", inner.as_bytes()]),
+        None => fnv1a(&[path.as_bytes()]),
+    };
+    Some(cache_dir()?.join(format!("{:016x}.json", key)))
+}
+
+/// 64-bit FNV-1a hash of concatenated `parts`. Unlike `DefaultHasher` it is same
+/// in all Rust versions, so hashes stored in cache stay valid
+fn fnv1a(parts: &[&[u8]]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_reference_values() {
+        assert_eq!(fnv1a(&[]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(&[b"a"]), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(&[b"foobar"]), 0x8594_4171_f739_67e8);
+        assert_eq!(fnv1a(&[b"foo", b"bar"]), fnv1a(&[b"foobar"]));
+    }
+}
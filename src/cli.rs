@@ -0,0 +1,276 @@
+use svdselector::{
+    archive, cache,
+    decode::{decode, find_register, parse_assignment},
+    dump::{self, Dump},
+    export::{export, Format},
+    pack,
+    registers::{parse_number, Entry, RegistersFile, Version},
+    Device, Lookup,
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::FILE;
+
+pub const USAGE: &str = "Usage:
+    svdselector [OPTIONS]                open GUI
+    svdselector [OPTIONS] add PATH [ALIAS]
+                                         add register or field to monitored list
+    svdselector [OPTIONS] remove PATH    remove register or field from list
+    svdselector [OPTIONS] list           print monitored registers and fields
+    svdselector [OPTIONS] export FORMAT [FILE]
+                                         print or write monitored items with SVD properties
+                                         (json, toml), GDB script with command for every
+                                         item (gdb), GDB script setting watchpoints on them
+                                         (watch, rwatch, awatch) or script printing them
+                                         for OpenOCD (openocd) or pyOCD (pyocd)
+    svdselector [OPTIONS] lookup ADDRESS find register and fields at memory address
+    svdselector [OPTIONS] decode PATH [=] VALUE
+                                         print fields and enumerated values of register value
+    svdselector [OPTIONS] dump FILE@ADDRESS...
+                                         print registers found in memory dumps with fields
+                                         (e.g. `dump binary memory FILE START END` of GDB)
+Options:
+    -C, --root DIR                       project root, other paths are relative to it
+    -o, --output FILE                    registers file (default: registers.txt)
+    --svd FILE                           SVD file (replaces SVD line of registers file),
+                                         can be .gz, .xz, zip or CMSIS-Pack
+    --svd-inner PATH                     path of SVD inside zip or CMSIS-Pack
+    --pack DIR --device NAME             use SVD of device from CMSIS-Packs in DIR
+                                         (unpacked pack, .pack files or pack cache)
+    --drop-invalid                       remove lines of registers file which can't be parsed
+    --format v1|v2                       format of saved registers file (default: v2)
+    --no-cache                           parse SVD even if it is cached";
+
+/// Options common for GUI and command line
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Directory to change to before other paths are used
+    pub root: Option<PathBuf>,
+    /// `registers.txt` read by dashboard
+    pub output: PathBuf,
+    pub svd: Option<String>,
+    pub svd_inner: Option<String>,
+    pub pack_dir: Option<String>,
+    pub device_name: Option<String>,
+    pub drop_invalid: bool,
+    pub use_cache: bool,
+    /// Format of saved file, `None` if not set
+    pub version: Option<Version>,
+    /// Command and its arguments. GUI is opened if empty
+    pub command: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            root: None,
+            output: PathBuf::from(FILE),
+            svd: None,
+            svd_inner: None,
+            pack_dir: None,
+            device_name: None,
+            drop_invalid: false,
+            use_cache: true,
+            version: None,
+            command: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Parse command line arguments. `None` if help is asked
+    pub fn parse(args: &[String]) -> anyhow::Result<Option<Self>> {
+        let mut options = Self::default();
+        let mut args = args.iter().cloned();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("{} requires value", arg));
+            match arg.as_str() {
+                "-C" | "--root" => options.root = Some(PathBuf::from(value()?)),
+                "-o" | "--output" => options.output = PathBuf::from(value()?),
+                "--svd" => options.svd = Some(value()?),
+                "--svd-inner" => options.svd_inner = Some(value()?),
+                "--pack" => options.pack_dir = Some(value()?),
+                "--device" => options.device_name = Some(value()?),
+                "--drop-invalid" => options.drop_invalid = true,
+                "--no-cache" => options.use_cache = false,
+                "--format" => options.version = Some(value()?.parse()?),
+                // `help` can also be path or alias of other command
+                "help" if !options.command.is_empty() => options.command.push(arg),
+                "-h" | "--help" | "help" => {
+                    println!("{}", USAGE);
+                    return Ok(None);
+                }
+                _ => options.command.push(arg),
+            }
+        }
+        Ok(Some(options))
+    }
+
+    /// Set SVD of registers file from `--svd`, `--svd-inner` or `--pack` and `--device`.
+    /// Single SVD of archive is chosen if path inside archive is not known
+    pub fn apply_svd(&self, file: &mut RegistersFile) -> anyhow::Result<()> {
+        if let Some(svd) = &self.svd {
+            file.svd = svd.clone();
+            file.svd_inner = None;
+        }
+        if self.svd_inner.is_some() {
+            file.svd_inner = self.svd_inner.clone();
+        }
+        match (&self.pack_dir, &self.device_name) {
+            (Some(dir), Some(name)) => {
+                let dir = Path::new(dir);
+                let (devices, errors) = pack::devices(dir)?;
+                for e in &errors {
+                    eprintln!("Warning: {:#}", e);
+                }
+                let found = pack::find_device(&devices, dir, name)?;
+                file.svd = found.path.to_string_lossy().into_owned();
+                file.svd_inner = found.inner;
+            }
+            (None, None) => {}
+            _ => bail!("--pack and --device must be used together"),
+        }
+        Ok(())
+    }
+}
+
+pub fn run(options: Options) -> anyhow::Result<()> {
+    let output = options.output.as_path();
+    let version = options.version.unwrap_or_default();
+    let use_cache = options.use_cache;
+    let free: Vec<&str> = options.command.iter().map(String::as_str).collect();
+
+    let mut file = read_registers_file(output)?;
+    if options.drop_invalid {
+        file.remove_invalid();
+    }
+    options.apply_svd(&mut file)?;
+    // `remove` and `list` only edit or print registers file
+    let loads_device = matches!(
+        free.first(),
+        Some(&"add") | Some(&"export") | Some(&"lookup") | Some(&"decode") | Some(&"dump")
+    );
+    if loads_device {
+        if file.svd.is_empty() {
+            bail!("{} not found, use --svd to set SVD file", output.display());
+        }
+        if file.svd_inner.is_none() && archive::is_archive(Path::new(&file.svd)) {
+            file.svd_inner = Some(archive::single_svd_entry(Path::new(&file.svd))?);
+        }
+    }
+    let svd_name = archive::display_name(Path::new(&file.svd), file.svd_inner.as_deref());
+
+    match free.as_slice() {
+        ["add", path] | ["add", path, _] => {
+            let alias = free.get(2).filter(|a| **a != "_").map(|a| a.to_string());
+            let device = load_device(&file, use_cache)?;
+            let node = device
+                .find(path)
+                .filter(|n| n.kind.is_selectable())
+                .ok_or_else(|| anyhow!("{} not found in {}", path, svd_name))?;
+            file.insert(Entry::from_node(node, alias));
+            file.write(output, version)?;
+        }
+        ["remove", path] => {
+            if !file.remove(path) {
+                bail!("{} is not monitored", path);
+            }
+            file.write(output, version)?;
+        }
+        ["list"] => {
+            println!("SVD File {}", svd_name);
+            for e in file.entries() {
+                println!("{}", e);
+            }
+        }
+        ["export", format] | ["export", format, _] => {
+            let format: Format = format.parse()?;
+            let device = load_device(&file, use_cache)?;
+            let doc = export(&file, Some(&device), format)?;
+            match free.get(2) {
+                Some(out) => {
+                    fs::write(out, doc).with_context(|| format!("Unable to write {}", out))?
+                }
+                None => print!("{}", doc),
+            }
+        }
+        ["lookup", address] => {
+            let address =
+                parse_number(address).ok_or_else(|| anyhow!("invalid address `{}`", address))?;
+            let device = load_device(&file, use_cache)?;
+            match device.lookup(address) {
+                Some(Lookup::Register { register, fields }) => {
+                    let range = register.address_range().unwrap();
+                    println!(
+                        "{}  0x{:08x}..0x{:08x}  byte {}",
+                        register.path,
+                        range.start,
+                        range.end,
+                        address - range.start
+                    );
+                    for f in fields {
+                        let (offset, width) = f.bits.unwrap_or_default();
+                        println!("    {}  [{}:{}]", f.path, offset + width - 1, offset);
+                    }
+                }
+                Some(Lookup::Block { peripheral, block }) => println!(
+                    "No register at 0x{:08x}, {} address block 0x{:08x}..0x{:08x}",
+                    address, peripheral.name, block.start, block.end
+                ),
+                None => bail!("0x{:08x} is not in any peripheral of {}", address, svd_name),
+            }
+        }
+        ["decode", args @ ..] if !args.is_empty() => {
+            // Value from bug report can be passed as one or several arguments
+            let text = args.join(" ");
+            let (path, value) = parse_assignment(&text)
+                .ok_or_else(|| anyhow!("expected PATH = VALUE, found `{}`", text))?;
+            let device = load_device(&file, use_cache)?;
+            let register = find_register(&device, path)
+                .ok_or_else(|| anyhow!("register {} not found in {}", path, svd_name))?;
+            print!("{}", decode(register, value));
+        }
+        ["dump", specs @ ..] if !specs.is_empty() => {
+            let dumps = specs
+                .iter()
+                .map(|spec| Dump::read_spec(spec))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let device = load_device(&file, use_cache)?;
+            print!("{}", dump::report(&device, &svd_name, &dumps));
+        }
+        _ => bail!("unknown command\n{}", USAGE),
+    }
+    Ok(())
+}
+
+fn load_device(file: &RegistersFile, use_cache: bool) -> anyhow::Result<Device> {
+    let svd_path = Path::new(&file.svd);
+    let inner = file.svd_inner.as_deref();
+    if use_cache {
+        let (device, error) = cache::load(svd_path, inner)?;
+        if let Some(e) = error {
+            eprintln!("Warning: {:#}", e);
+        }
+        Ok(device)
+    } else {
+        Device::load(svd_path, inner)
+    }
+}
+
+/// Reads registers file if it exists and reports lines which can't be parsed
+fn read_registers_file(path: &Path) -> anyhow::Result<RegistersFile> {
+    if !path.exists() {
+        return Ok(RegistersFile::default());
+    }
+    let (file, errors) = RegistersFile::read(path)?;
+    for e in &errors {
+        eprintln!("Warning: {} {}", path.display(), e);
+    }
+    Ok(file)
+}
@@ -0,0 +1,248 @@
+//! Decoding raw register value into fields, e.g. `RCC.CFGR = 0x0040840A` from bug report
+
+use crate::registers::{field_mask, parse_number};
+use crate::{Device, EnumValue, Kind, Node};
+
+use std::fmt;
+
+/// Register value split into fields
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decoded<'a> {
+    pub register: &'a Node,
+    pub value: u64,
+    /// Fields from high bits to low bits
+    pub fields: Vec<DecodedField<'a>>,
+    /// Set bits outside of register size, or which don't belong to any field
+    /// (if register has fields)
+    pub undefined: u64,
+}
+
+/// Value of field with its enumerated value
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedField<'a> {
+    pub field: &'a Node,
+    pub value: u64,
+    /// Enumerated value with this value, or default one (without value)
+    pub enum_value: Option<&'a EnumValue>,
+}
+
+/// Split `value` of `register` into its fields
+pub fn decode(register: &Node, value: u64) -> Decoded<'_> {
+    let mut fields: Vec<DecodedField> = register
+        .iter()
+        .filter(|n| n.kind == Kind::Field)
+        .filter_map(|field| {
+            let (offset, width) = field.bits?;
+            let value = value.checked_shr(offset).unwrap_or_default() & field_mask(width);
+            let values = field.enum_values.iter().flat_map(|evs| &evs.values);
+            let enum_value = values
+                .clone()
+                .find(|ev| ev.value == Some(value))
+                .or_else(|| values.clone().find(|ev| ev.value.is_none()));
+            Some(DecodedField {
+                field,
+                value,
+                enum_value,
+            })
+        })
+        .collect();
+    fields.sort_by_key(|f| std::cmp::Reverse(f.field.bits));
+    let size = register.properties.size.unwrap_or(32);
+    // Whole value of register without fields is data
+    let defined = if fields.is_empty() {
+        u64::MAX
+    } else {
+        fields.iter().fold(0, |mask, f| {
+            let (offset, width) = f.field.bits.unwrap_or_default();
+            mask | field_mask(width).checked_shl(offset).unwrap_or_default()
+        })
+    };
+    Decoded {
+        register,
+        value,
+        fields,
+        undefined: value & !(defined & field_mask(size)),
+    }
+}
+
+/// Register by path as written in C code or logs: `RCC.CFGR`, `RCC->CFGR` or `rcc.cfgr`
+pub fn find_register<'a>(device: &'a Device, path: &str) -> Option<&'a Node> {
+    let path = path.trim().replace("->", ".");
+    let is_register = |n: &&Node| n.kind == Kind::Register;
+    device.find(&path).filter(is_register).or_else(|| {
+        device
+            .iter()
+            .filter(is_register)
+            .find(|n| n.path.eq_ignore_ascii_case(&path))
+    })
+}
+
+/// Split text like `RCC.CFGR = 0x0040840A`, `RCC.CFGR: 0x0040840A` or
+/// `RCC.CFGR 0x0040840A` into register path and value
+pub fn parse_assignment(text: &str) -> Option<(&str, u64)> {
+    let (path, value) = text.trim().rsplit_once(|c: char| "=: \t".contains(c))?;
+    let path = path.trim_end_matches(|c: char| "=: \t".contains(c));
+    Some((path, parse_number(value).filter(|_| !path.is_empty())?))
+}
+
+impl fmt::Display for Decoded<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.register.properties.size.unwrap_or(32).div_ceil(4) as usize;
+        writeln!(
+            f,
+            "{} = 0x{:0digits$x}",
+            self.register.path,
+            self.value,
+            digits = digits
+        )?;
+        for d in &self.fields {
+            let (offset, width) = d.field.bits.unwrap_or_default();
+            let bits = match width {
+                1 => format!("[{}]", offset),
+                _ => format!("[{}:{}]", offset + width - 1, offset),
+            };
+            write!(f, "    {:<8} {} = {}", bits, d.field.name, d.value)?;
+            if width > 4 {
+                write!(f, " (0x{:x})", d.value)?;
+            }
+            if let Some(ev) = d.enum_value {
+                write!(f, " {}", ev.name)?;
+                if !ev.description.is_empty() {
+                    write!(f, ": {}", ev.description)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        if self.undefined != 0 {
+            writeln!(
+                f,
+                "    undefined bits set: 0x{:0digits$x}",
+                self.undefined,
+                digits = digits
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device() -> Device {
+        Device::parse(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<device>
+  <name>TEST</name>
+  <addressUnitBits>8</addressUnitBits>
+  <width>32</width>
+  <size>32</size>
+  <peripherals>
+    <peripheral>
+      <name>RCC</name>
+      <baseAddress>0x40021000</baseAddress>
+      <registers>
+        <register>
+          <name>CFGR</name><addressOffset>0x4</addressOffset><size>16</size>
+          <fields>
+            <field>
+              <name>SW</name><bitOffset>0</bitOffset><bitWidth>2</bitWidth>
+              <enumeratedValues>
+                <enumeratedValue><name>HSI</name><description>HSI selected</description><value>0</value></enumeratedValue>
+                <enumeratedValue><name>HSE</name><value>1</value></enumeratedValue>
+                <enumeratedValue><name>RESERVED</name><isDefault>true</isDefault></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field><name>MCO</name><bitOffset>4</bitOffset><bitWidth>5</bitWidth></field>
+          </fields>
+        </register>
+        <register><name>DATA</name><addressOffset>0x8</addressOffset><size>8</size></register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn assignment_forms() {
+        for text in [
+            "RCC.CFGR = 0x0040840A",
+            "RCC.CFGR: 0x0040840A",
+            "  RCC->CFGR 0x0040840A\n",
+        ] {
+            let (path, value) = parse_assignment(text).unwrap();
+            assert_eq!(path.replace("->", "."), "RCC.CFGR", "{}", text);
+            assert_eq!(value, 0x0040_840a, "{}", text);
+        }
+        assert_eq!(parse_assignment("RCC.CFGR = 10"), Some(("RCC.CFGR", 10)));
+        assert_eq!(parse_assignment("0x10"), None);
+        assert_eq!(parse_assignment("RCC.CFGR = x"), None);
+    }
+
+    #[test]
+    fn register_paths() {
+        let d = device();
+        for path in ["RCC.CFGR", "RCC->CFGR", "rcc.cfgr", " Rcc->Cfgr "] {
+            let r = find_register(&d, path);
+            assert_eq!(r.map(|r| r.path.as_str()), Some("RCC.CFGR"), "{}", path);
+        }
+        assert!(find_register(&d, "RCC").is_none());
+        assert!(find_register(&d, "RCC.CFGR.SW").is_none());
+    }
+
+    #[test]
+    fn fields_and_enum_values() {
+        let d = device();
+        let cfgr = find_register(&d, "RCC.CFGR").unwrap();
+        let fields = |value| -> Vec<(String, u64, Option<String>)> {
+            decode(cfgr, value)
+                .fields
+                .iter()
+                .map(|f| {
+                    let ev = f.enum_value.map(|ev| ev.name.clone());
+                    (f.field.name.clone(), f.value, ev)
+                })
+                .collect()
+        };
+        assert_eq!(
+            fields(0x1f1),
+            [
+                ("MCO".to_string(), 0x1f, None),
+                ("SW".to_string(), 1, Some("HSE".to_string())),
+            ]
+        );
+        // Value without its own enumerated value gets default one
+        assert_eq!(
+            fields(0x3)[1],
+            ("SW".to_string(), 3, Some("RESERVED".to_string()))
+        );
+        assert_eq!(decode(cfgr, 0x1f1).undefined, 0);
+
+        assert_eq!(
+            decode(cfgr, 0x0040_840a).to_string(),
+            "RCC.CFGR = 0x40840a\n\
+             \x20   [8:4]    MCO = 0 (0x0)\n\
+             \x20   [1:0]    SW = 2 RESERVED\n\
+             \x20   undefined bits set: 0x408408\n"
+        );
+    }
+
+    #[test]
+    fn undefined_bits() {
+        let d = device();
+        let cfgr = find_register(&d, "RCC.CFGR").unwrap();
+        // Bits between fields and above 16-bit size
+        assert_eq!(decode(cfgr, 0x1_000c).undefined, 0x1_000c);
+        assert_eq!(decode(cfgr, 0x0200).undefined, 0x0200);
+
+        // Whole register without fields is defined up to its size
+        let data = find_register(&d, "RCC.DATA").unwrap();
+        let decoded = decode(data, 0x1ff);
+        assert!(decoded.fields.is_empty());
+        assert_eq!(decoded.undefined, 0x100);
+        assert_eq!(decode(data, 0xff).undefined, 0);
+        assert_eq!(decode(data, 0xff).to_string(), "RCC.DATA = 0xff\n");
+    }
+}
@@ -0,0 +1,328 @@
+use crate::svd::{
+    self, Cluster, ClusterInfo, DeriveFrom, Field, FieldInfo, Register, RegisterCluster,
+    RegisterInfo,
+};
+use crate::tree::ReadActions;
+use anyhow::{anyhow, bail};
+
+/// Copy of device where peripherals, registers, clusters and fields with `derivedFrom`
+/// have inherited properties of their base elements.
+///
+/// `ra` holds read actions of elements as written in SVD. They are resolved the same way
+/// and returned keyed by paths of expanded device
+pub(crate) fn expand_derived(
+    device: &svd::Device,
+    ra: &ReadActions,
+) -> anyhow::Result<(svd::Device, ReadActions)> {
+    let mut merged = device.clone();
+    let mut merged_ra = ReadActions::new();
+    for p in &mut merged.peripherals {
+        let (derived, pra) = derive_peripheral(device, ra, p, 0)?;
+        *p = derived;
+        merged_ra.extend(
+            pra.into_iter()
+                .map(|(k, a)| (format!("{}.{}", p.name, k), a)),
+        );
+    }
+    let mut expanded = merged.clone();
+    let mut expanded_ra = ReadActions::new();
+    for p in &mut expanded.peripherals {
+        if let Some(rcs) = &mut p.registers {
+            let scope = std::slice::from_ref(&p.name);
+            for rc in rcs.iter() {
+                let rcra = rc_read_actions(&merged, &merged_ra, scope, rc, 0);
+                let path = format!("{}.{}", p.name, rc_name(rc));
+                expanded_ra.extend(rcra.into_iter().map(|(k, a)| (format!("{}{}", path, k), a)));
+            }
+            expand_children(&merged, rcs, scope)?;
+        }
+    }
+    Ok((expanded, expanded_ra))
+}
+
+/// Registers of derived peripheral override registers of base peripheral
+/// with same name or are added after them. Address blocks are inherited if not set.
+///
+/// Returns also read actions of resulting registers keyed by path relative to peripheral
+fn derive_peripheral(
+    device: &svd::Device,
+    ra: &ReadActions,
+    p: &svd::Peripheral,
+    depth: usize,
+) -> anyhow::Result<(svd::Peripheral, ReadActions)> {
+    let prefix = format!("{}.", p.name);
+    let own_ra: ReadActions = ra
+        .iter()
+        .filter_map(|(k, a)| Some((k.strip_prefix(&prefix)?.to_string(), *a)))
+        .collect();
+    let base = match &p.derived_from {
+        Some(dname) => device
+            .peripherals
+            .iter()
+            .find(|b| &b.name == dname)
+            .ok_or_else(|| anyhow!("{}: derivedFrom {} not found", p.name, dname))?,
+        None => return Ok((p.clone(), own_ra)),
+    };
+    if depth > 16 {
+        bail!("{}: derivedFrom loop", p.name);
+    }
+    let (base, base_ra) = derive_peripheral(device, ra, base, depth + 1)?;
+    // Overridden registers don't keep anything of base ones
+    let own_names: Vec<&str> = p.registers.iter().flatten().map(rc_name).collect();
+    let mut derived_ra: ReadActions = base_ra
+        .into_iter()
+        .filter(|(k, _)| !own_names.contains(&k.split('.').next().unwrap_or_default()))
+        .collect();
+    derived_ra.extend(own_ra);
+    let mut derived = p.derive_from(&base);
+    if derived.address_block.is_none() {
+        derived.address_block = base.address_block.clone();
+    }
+    if let (Some(rcs), Some(own)) = (&base.registers, &p.registers) {
+        let mut rcs = rcs.clone();
+        for rc in own {
+            match rcs.iter_mut().find(|b| rc_name(b) == rc_name(rc)) {
+                Some(b) => *b = rc.clone(),
+                None => rcs.push(rc.clone()),
+            }
+        }
+        derived.registers = Some(rcs);
+    }
+    Ok((derived, derived_ra))
+}
+
+fn expand_children(
+    device: &svd::Device,
+    rcs: &mut [RegisterCluster],
+    scope: &[String],
+) -> anyhow::Result<()> {
+    for rc in rcs {
+        // Inherited children and fields refer to names in scope of base element
+        let inherits = match rc {
+            RegisterCluster::Register(r) => r.fields.is_none(),
+            RegisterCluster::Cluster(c) => c.children.is_empty(),
+        };
+        let base_scope = rc_derived_from(rc)
+            .filter(|_| inherits)
+            .and_then(|dname| find_rc(device, scope, dname));
+        let base_scope = base_scope.map(|(mut bscope, base)| {
+            if let RegisterCluster::Cluster(_) = base {
+                bscope.push(rc_name(base).to_string());
+            }
+            bscope
+        });
+        *rc = derive_rc(device, scope, rc, 0)?;
+        match rc {
+            RegisterCluster::Register(r) => {
+                expand_fields(device, base_scope.as_deref().unwrap_or(scope), r)?
+            }
+            RegisterCluster::Cluster(c) => {
+                let scope = base_scope.unwrap_or_else(|| {
+                    let mut scope = scope.to_vec();
+                    scope.push(c.name.clone());
+                    scope
+                });
+                expand_children(device, &mut c.children, &scope)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rc_name(rc: &RegisterCluster) -> &str {
+    match rc {
+        RegisterCluster::Register(r) => &r.name,
+        RegisterCluster::Cluster(c) => &c.name,
+    }
+}
+
+fn rc_derived_from(rc: &RegisterCluster) -> Option<&String> {
+    match rc {
+        RegisterCluster::Register(r) => r.derived_from.as_ref(),
+        RegisterCluster::Cluster(c) => c.derived_from.as_ref(),
+    }
+}
+
+/// Register or cluster by full path starting from peripheral name
+fn get_rc<'a>(device: &'a svd::Device, path: &[&str]) -> Option<&'a RegisterCluster> {
+    let (pname, rest) = path.split_first()?;
+    let p = device.peripherals.iter().find(|p| p.name == *pname)?;
+    let mut children = p.registers.as_deref()?;
+    let (name, cpath) = rest.split_last()?;
+    for cname in cpath {
+        children = match children.iter().find(|rc| rc_name(rc) == *cname)? {
+            RegisterCluster::Cluster(c) => &c.children,
+            RegisterCluster::Register(_) => return None,
+        };
+    }
+    children.iter().find(|rc| rc_name(rc) == *name)
+}
+
+/// Find register or cluster referenced by `derivedFrom`.
+///
+/// Name is searched relative to current cluster, then to outer clusters and
+/// peripheral, and at last as absolute path. Returns found element with its scope.
+fn find_rc<'a>(
+    device: &'a svd::Device,
+    scope: &[String],
+    dname: &str,
+) -> Option<(Vec<String>, &'a RegisterCluster)> {
+    let dpath: Vec<&str> = dname.split('.').collect();
+    (0..=scope.len()).rev().find_map(|i| {
+        let path: Vec<&str> = scope[..i]
+            .iter()
+            .map(String::as_str)
+            .chain(dpath.iter().copied())
+            .collect();
+        get_rc(device, &path).map(|rc| {
+            let bscope = path[..path.len() - 1].iter().map(|s| s.to_string());
+            (bscope.collect(), rc)
+        })
+    })
+}
+
+fn derive_rc(
+    device: &svd::Device,
+    scope: &[String],
+    rc: &RegisterCluster,
+    depth: usize,
+) -> anyhow::Result<RegisterCluster> {
+    let dname = match rc_derived_from(rc) {
+        Some(dname) => dname,
+        None => return Ok(rc.clone()),
+    };
+    let path = format!("{}.{}", scope.join("."), rc_name(rc));
+    if depth > 16 {
+        bail!("{}: derivedFrom loop", path);
+    }
+    let (bscope, base) = find_rc(device, scope, dname)
+        .ok_or_else(|| anyhow!("{}: derivedFrom {} not found", path, dname))?;
+    let base = derive_rc(device, &bscope, base, depth + 1)?;
+    Ok(match (rc, &base) {
+        // Element keeps its own dimension, only properties are inherited
+        (RegisterCluster::Register(r), RegisterCluster::Register(b)) => {
+            let info = RegisterInfo::derive_from(r, b);
+            RegisterCluster::Register(match r {
+                Register::Single(_) => Register::Single(info),
+                Register::Array(_, dim) => Register::Array(info, dim.clone()),
+            })
+        }
+        (RegisterCluster::Cluster(c), RegisterCluster::Cluster(b)) => {
+            let info = ClusterInfo::derive_from(c, b);
+            RegisterCluster::Cluster(match c {
+                Cluster::Single(_) => Cluster::Single(info),
+                Cluster::Array(_, dim) => Cluster::Array(info, dim.clone()),
+            })
+        }
+        _ => bail!("{}: derivedFrom {} has different type", path, dname),
+    })
+}
+
+/// Read actions of register or cluster and its descendants after derivation,
+/// keyed by path relative to element (empty for element itself).
+///
+/// Mirrors [`derive_rc`], [`expand_children`] and [`expand_fields`]
+/// which report errors in `derivedFrom`
+fn rc_read_actions(
+    device: &svd::Device,
+    ra: &ReadActions,
+    scope: &[String],
+    rc: &RegisterCluster,
+    depth: usize,
+) -> ReadActions {
+    let path = format!("{}.{}", scope.join("."), rc_name(rc));
+    let base = rc_derived_from(rc)
+        .filter(|_| depth <= 16)
+        .and_then(|dname| find_rc(device, scope, dname));
+    let base_ra = base
+        .map(|(bscope, base)| rc_read_actions(device, ra, &bscope, base, depth + 1))
+        .unwrap_or_default();
+    let inherited = || {
+        base_ra
+            .iter()
+            .filter(|(k, _)| !k.is_empty())
+            .map(|(k, a)| (k.clone(), *a))
+    };
+    let mut result = ReadActions::new();
+    match rc {
+        RegisterCluster::Register(r) => {
+            if let Some(a) = ra.get(&path).or_else(|| base_ra.get("")) {
+                result.insert(String::new(), *a);
+            }
+            match &r.fields {
+                Some(fields) => {
+                    for f in fields {
+                        let own = ra.get(&format!("{}.{}", path, f.name));
+                        let derived = || {
+                            let dname = f.derived_from.as_ref()?;
+                            let base_path = match dname.rsplit_once('.') {
+                                None => format!("{}.{}", path, dname),
+                                Some((rname, fname)) => {
+                                    let (bscope, b) = find_rc(device, scope, rname)?;
+                                    format!("{}.{}.{}", bscope.join("."), rc_name(b), fname)
+                                }
+                            };
+                            ra.get(&base_path)
+                        };
+                        if let Some(a) = own.or_else(derived) {
+                            result.insert(format!(".{}", f.name), *a);
+                        }
+                    }
+                }
+                None => result.extend(inherited()),
+            }
+        }
+        RegisterCluster::Cluster(c) if c.children.is_empty() => result.extend(inherited()),
+        RegisterCluster::Cluster(c) => {
+            let mut scope = scope.to_vec();
+            scope.push(c.name.clone());
+            for child in &c.children {
+                let child_ra = rc_read_actions(device, ra, &scope, child, 0);
+                let cpath = format!(".{}", rc_name(child));
+                result.extend(
+                    child_ra
+                        .into_iter()
+                        .map(|(k, a)| (format!("{}{}", cpath, k), a)),
+                );
+            }
+        }
+    }
+    result
+}
+
+/// Derive fields from other fields of same register (`FIELD`)
+/// or from fields of other register (`REGISTER.FIELD`)
+fn expand_fields(
+    device: &svd::Device,
+    scope: &[String],
+    r: &mut RegisterInfo,
+) -> anyhow::Result<()> {
+    let rpath = format!("{}.{}", scope.join("."), r.name);
+    let fields = match &mut r.fields {
+        Some(fields) => fields,
+        None => return Ok(()),
+    };
+    let siblings = fields.clone();
+    for f in fields.iter_mut() {
+        if let Some(dname) = &f.derived_from {
+            let base = match dname.rsplit_once('.') {
+                None => siblings.iter().find(|b| &b.name == dname),
+                Some((rname, fname)) => match find_rc(device, scope, rname) {
+                    Some((_, RegisterCluster::Register(b))) => b
+                        .fields
+                        .as_ref()
+                        .and_then(|bf| bf.iter().find(|b| b.name == fname)),
+                    _ => None,
+                },
+            };
+            let base: &Field = base
+                .ok_or_else(|| anyhow!("{}.{}: derivedFrom {} not found", rpath, f.name, dname))?;
+            let info = FieldInfo::derive_from(f, base);
+            *f = match f {
+                Field::Single(_) => Field::Single(info),
+                Field::Array(_, dim) => Field::Array(info, dim.clone()),
+            };
+        }
+    }
+    Ok(())
+}
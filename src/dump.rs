@@ -0,0 +1,216 @@
+//! Post-mortem report of peripheral registers from memory dumps.
+//!
+//! Dump is raw binary file with base address, like file of GDB
+//! `dump binary memory usart1.bin 0x40011000 0x40011400`. Every register of SVD which lies
+//! inside dumped memory is decoded into fields. Values are little-endian like on Cortex-M.
+
+use crate::decode::decode;
+use crate::registers::parse_number;
+use crate::{Device, Kind, Node};
+
+use anyhow::{anyhow, Context};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{fmt::Write, fs};
+
+/// Dumped memory
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dump {
+    pub path: PathBuf,
+    pub base: u64,
+    pub data: Vec<u8>,
+}
+
+impl Dump {
+    /// Read dump from argument `FILE@ADDRESS`
+    pub fn read_spec(spec: &str) -> anyhow::Result<Self> {
+        let (path, base) = spec
+            .rsplit_once('@')
+            .ok_or_else(|| anyhow!("expected FILE@ADDRESS, found `{}`", spec))?;
+        let base = parse_number(base).ok_or_else(|| anyhow!("invalid address `{}`", base))?;
+        Self::read(Path::new(path), base)
+    }
+
+    pub fn read(path: &Path, base: u64) -> anyhow::Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            base,
+            data,
+        })
+    }
+
+    /// Addresses of dumped memory
+    pub fn range(&self) -> Range<u64> {
+        self.base..self.base + self.data.len() as u64
+    }
+
+    /// Little-endian value of memory range if it is dumped
+    pub fn value(&self, range: &Range<u64>) -> Option<u64> {
+        if range.start < self.base || range.end > self.range().end {
+            return None;
+        }
+        let start = (range.start - self.base) as usize;
+        let bytes = &self.data[start..start + (range.end - range.start).min(8) as usize];
+        Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, byte| value << 8 | *byte as u64),
+        )
+    }
+}
+
+/// Registers of `device` found in dumps with their values, sorted by address
+pub fn registers<'a>(device: &'a Device, dumps: &[Dump]) -> Vec<(&'a Node, u64)> {
+    let mut found: Vec<(&Node, u64)> = device
+        .iter()
+        .filter(|n| n.kind == Kind::Register)
+        .filter_map(|register| {
+            let range = register.address_range()?;
+            let value = dumps.iter().find_map(|d| d.value(&range))?;
+            Some((register, value))
+        })
+        .collect();
+    found.sort_by_key(|(register, _)| register.address);
+    found
+}
+
+/// Text report with all registers found in dumps, grouped by peripheral
+pub fn report(device: &Device, svd_name: &str, dumps: &[Dump]) -> String {
+    let mut s = format!("Peripheral registers of {} ({})\n", device.name, svd_name);
+    for d in dumps {
+        let range = d.range();
+        writeln!(
+            s,
+            "Dump {}: 0x{:08x}..0x{:08x}",
+            d.path.display(),
+            range.start,
+            range.end
+        )
+        .unwrap();
+    }
+    let found = registers(device, dumps);
+    writeln!(s, "{} registers found", found.len()).unwrap();
+    let mut peripheral = "";
+    for (register, value) in found {
+        let pname = register.path.split('.').next().unwrap_or_default();
+        if pname != peripheral {
+            peripheral = pname;
+            writeln!(s, "\n{}", pname).unwrap();
+        }
+        if let Some(ra) = register.read_side_effect() {
+            writeln!(
+                s,
+                "# reading changes device state (readAction {}), dumping could change it",
+                ra.as_str()
+            )
+            .unwrap();
+        }
+        s += &decode(register, value).to_string();
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump(base: u64, data: &[u8]) -> Dump {
+        Dump {
+            path: PathBuf::from("dump.bin"),
+            base,
+            data: data.to_vec(),
+        }
+    }
+
+    fn device() -> Device {
+        let register = |name: &str, offset: u32, size: u32| {
+            format!(
+                "<register><name>{}</name><addressOffset>{:#x}</addressOffset><size>{}</size></register>",
+                name, offset, size
+            )
+        };
+        let peripheral = |name: &str, base: u32, registers: &str| {
+            format!(
+                "<peripheral><name>{}</name><baseAddress>{:#x}</baseAddress><registers>{}</registers></peripheral>",
+                name, base, registers
+            )
+        };
+        Device::parse(&format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<device>
+  <name>TEST</name>
+  <addressUnitBits>8</addressUnitBits>
+  <width>32</width>
+  <size>32</size>
+  <peripherals>{}{}</peripherals>
+</device>"#,
+            peripheral(
+                "P",
+                0x4000_0000,
+                &[
+                    register("B", 0, 8),
+                    register("H", 2, 16),
+                    register("W", 4, 32),
+                    register("END", 8, 32),
+                ]
+                .concat()
+            ),
+            peripheral("Q", 0x4000_1000, &register("W", 0, 32)),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn little_endian_values() {
+        let d = dump(0x100, &[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]);
+        assert_eq!(d.value(&(0x100..0x101)), Some(0x11));
+        assert_eq!(d.value(&(0x102..0x104)), Some(0x4433));
+        assert_eq!(d.value(&(0x104..0x108)), Some(0x8877_6655));
+        assert_eq!(d.value(&(0x100..0x108)), Some(0x8877_6655_4433_2211));
+        assert_eq!(d.value(&(0x106..0x10a)), None);
+        assert_eq!(d.value(&(0xfe..0x102)), None);
+    }
+
+    #[test]
+    fn registers_in_dumps() {
+        let device = device();
+        // `P.END` is only partially dumped
+        let dumps = [
+            dump(0x4000_1000, &[0x78, 0x56, 0x34, 0x12]),
+            dump(0x4000_0000, &[0xab, 0, 0xcd, 0xef, 1, 2, 3, 4, 0xff, 0xff]),
+        ];
+        let found: Vec<(&str, u64)> = registers(&device, &dumps)
+            .into_iter()
+            .map(|(r, value)| (r.path.as_str(), value))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("P.B", 0xab),
+                ("P.H", 0xefcd),
+                ("P.W", 0x0403_0201),
+                ("Q.W", 0x1234_5678),
+            ]
+        );
+    }
+
+    #[test]
+    fn dump_spec() {
+        let path = std::env::temp_dir().join(format!("svd@dump-{}.bin", std::process::id()));
+        fs::write(&path, [1, 2]).unwrap();
+        let spec = format!("{}@0x40011000", path.display());
+        let d = Dump::read_spec(&spec);
+        fs::remove_file(&path).unwrap();
+        let d = d.unwrap();
+        assert_eq!(d.path, path);
+        assert_eq!(d.base, 0x4001_1000);
+        assert_eq!(d.range(), 0x4001_1000..0x4001_1002);
+
+        let e = Dump::read_spec("dump.bin").unwrap_err();
+        assert_eq!(e.to_string(), "expected FILE@ADDRESS, found `dump.bin`");
+        let e = Dump::read_spec("dump.bin@base").unwrap_err();
+        assert_eq!(e.to_string(), "invalid address `base`");
+    }
+}
@@ -0,0 +1,163 @@
+//! Structured description of monitored registers and fields for other tools,
+//! or GDB, OpenOCD and pyOCD scripts for them
+
+use crate::gdb::{self, Watch};
+use crate::registers::{Entry, RegistersFile};
+use crate::{openocd, pyocd, Device};
+
+use anyhow::anyhow;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Format of exported document
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    /// GDB user commands, see [`gdb`]
+    Gdb,
+    /// GDB script setting watchpoints, see [`gdb::watchpoints`]
+    Watch(Watch),
+    /// OpenOCD Tcl procedure, see [`openocd`]
+    OpenOcd,
+    /// pyOCD user script, see [`pyocd`]
+    PyOcd,
+}
+
+impl Format {
+    /// File name extension
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+            Self::Gdb | Self::Watch(_) => "gdb",
+            Self::OpenOcd => "tcl",
+            Self::PyOcd => "py",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "toml" => Ok(Self::Toml),
+            "gdb" => Ok(Self::Gdb),
+            "openocd" => Ok(Self::OpenOcd),
+            "pyocd" => Ok(Self::PyOcd),
+            _ => s
+                .parse()
+                .map(Self::Watch)
+                .map_err(|_| anyhow!("unknown export format `{}`", s)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    svd: &'a str,
+    /// Path of SVD inside archive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    svd_inner: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<&'a str>,
+    entries: Vec<Item<'a>>,
+}
+
+/// Monitored register or field with properties from SVD
+#[derive(Serialize)]
+struct Item<'a> {
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<&'a str>,
+    address: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bit_offset: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bit_width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    access: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reset_value: Option<u64>,
+    /// `readAction` of register or its fields. Such items shouldn't be read automatically
+    #[serde(skip_serializing_if = "Option::is_none")]
+    read_action: Option<&'static str>,
+    /// `modifiedWriteValues` of field or fields of register except `modify`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified_write_values: Option<&'static str>,
+    /// `false` if entry is not found in SVD, so only values from `registers.txt` are known
+    found: bool,
+    // Tables must be after values in TOML
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    enum_values: Vec<Value<'a>>,
+}
+
+#[derive(Serialize)]
+struct Value<'a> {
+    name: &'a str,
+    value: u64,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    description: &'a str,
+}
+
+impl<'a> Item<'a> {
+    fn new(entry: &'a Entry, device: Option<&'a Device>) -> Self {
+        let node = device
+            .and_then(|d| d.find(&entry.path))
+            .filter(|n| n.kind.is_selectable());
+        let props = node.map(|n| n.properties).unwrap_or_default();
+        Self {
+            path: &entry.path,
+            alias: entry.alias.as_deref(),
+            address: entry.address,
+            size: props.size.or(entry.size),
+            bit_offset: entry.bits.map(|b| b.0),
+            bit_width: entry.bits.map(|b| b.1),
+            access: props.access.map(|a| a.as_str()),
+            reset_value: props.reset_value,
+            read_action: node
+                .and_then(|n| n.read_side_effect())
+                .or(entry.read_action)
+                .map(|ra| ra.as_str()),
+            modified_write_values: node.and_then(|n| n.write_side_effect()).map(|m| m.as_str()),
+            found: node.is_some(),
+            enum_values: node
+                .iter()
+                .flat_map(|n| &n.enum_values)
+                .flat_map(|evs| &evs.values)
+                .filter_map(|ev| {
+                    Some(Value {
+                        name: &ev.name,
+                        value: ev.value?,
+                        description: &ev.description,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Document with all entries of `registers.txt`. Properties are taken from `device`
+pub fn export(
+    file: &RegistersFile,
+    device: Option<&Device>,
+    format: Format,
+) -> anyhow::Result<String> {
+    let doc = Document {
+        svd: &file.svd,
+        svd_inner: file.svd_inner.as_deref(),
+        device: device.map(|d| d.name.as_str()),
+        entries: file.entries().map(|e| Item::new(e, device)).collect(),
+    };
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(&doc)? + "\n",
+        Format::Toml => toml::to_string(&doc)?,
+        Format::Gdb => gdb::script(file, device),
+        Format::Watch(kind) => gdb::watchpoints(file.entries(), kind),
+        Format::OpenOcd => openocd::script(file),
+        Format::PyOcd => pyocd::script(file),
+    })
+}
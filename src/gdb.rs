@@ -0,0 +1,266 @@
+//! GDB script with user command for every monitored register and field.
+//!
+//! Script doesn't need gdb-dashboard: after `source registers.gdb` command like
+//! `svd_usart1_sr` prints register value, field commands also decode enumerated values.
+//! `svd_registers` prints all of them.
+//!
+//! [`watchpoints`] makes script setting hardware watchpoints on monitored items.
+
+use crate::registers::{field_mask, Entry, RegistersFile};
+use crate::{Device, Node};
+
+use anyhow::anyhow;
+use std::str::FromStr;
+
+/// Command printing all monitored items
+pub const ALL_COMMAND: &str = "svd_registers";
+
+/// Script defining commands for all entries of `registers.txt`. Descriptions and
+/// enumerated values are taken from `device`. Command printing all entries skips
+/// ones with `readAction`, their own commands have to be called explicitly
+pub fn script(file: &RegistersFile, device: Option<&Device>) -> String {
+    let mut s = format!(
+        "# GDB commands generated by svdselector\n# SVD: {}\n# Use: source this file, then `{}` or command of register\n",
+        match &file.svd_inner {
+            Some(inner) => format!("{}!{}", file.svd, inner),
+            None => file.svd.clone(),
+        },
+        ALL_COMMAND
+    );
+    let mut names: Vec<String> = Vec::new();
+    let mut read_actions = Vec::new();
+    for entry in file.entries() {
+        let mut name = command_name(entry);
+        let taken = |name: &String| name == ALL_COMMAND || names.contains(name);
+        if taken(&name) {
+            let n = (2..).find(|n| !taken(&format!("{}_{}", name, n)));
+            name = format!("{}_{}", name, n.unwrap());
+        }
+        let node = device.and_then(|d| d.find(&entry.path));
+        s += "\n";
+        s += &command(&name, entry, node);
+        names.push(name);
+        read_actions.push(entry.read_action);
+    }
+    s += &format!("\ndefine {}\n", ALL_COMMAND);
+    for (name, ra) in names.iter().zip(&read_actions) {
+        match ra {
+            Some(ra) => {
+                s += &format!(
+                    "  # {} isn't called, reading changes device state (readAction {})\n",
+                    name,
+                    ra.as_str()
+                )
+            }
+            None => s += &format!("  {}\n", name),
+        }
+    }
+    s += &format!(
+        "end\ndocument {}\nPrint all registers and fields selected in svdselector\nend\n",
+        ALL_COMMAND
+    );
+    s
+}
+
+/// Kind of hardware watchpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    /// `watch`, stops when value is changed by write
+    Write,
+    /// `rwatch`
+    Read,
+    /// `awatch`, stops on read and write
+    Access,
+}
+
+impl Watch {
+    /// GDB command
+    pub fn command(self) -> &'static str {
+        match self {
+            Self::Write => "watch",
+            Self::Read => "rwatch",
+            Self::Access => "awatch",
+        }
+    }
+}
+
+impl FromStr for Watch {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "watch" => Ok(Self::Write),
+            "rwatch" => Ok(Self::Read),
+            "awatch" => Ok(Self::Access),
+            _ => Err(anyhow!("unknown watchpoint `{}`", s)),
+        }
+    }
+}
+
+/// Script setting watchpoint of `kind` on every entry. Watched value has type of
+/// register size, so hardware compares same bytes as register access. Write watchpoint
+/// of field stops only when bits of field change, unless reading it has side effects
+pub fn watchpoints<'a>(entries: impl IntoIterator<Item = &'a Entry>, kind: Watch) -> String {
+    let mut s = format!(
+        "# GDB {} watchpoints generated by svdselector\n\
+         # Number of hardware watchpoints is limited (4 on most Cortex-M)\n",
+        kind.command()
+    );
+    for (n, entry) in entries.into_iter().enumerate() {
+        let size = entry.size.unwrap_or(32);
+        let register = format!("*({} *)0x{:08x}", c_type(entry), entry.address);
+        s += &format!("\n# {}\n", entry.path);
+        match (entry.bits, kind) {
+            (None, _) => s += &format!("{} {}\n", kind.command(), register),
+            (Some(_), Watch::Write) if entry.read_action.is_some() => {
+                s += &format!(
+                    "# condition would read register (readAction {}), whole register is watched\n\
+                     watch {}\n",
+                    entry.read_action.unwrap().as_str(),
+                    register
+                )
+            }
+            (Some(_), Watch::Write) => {
+                let var = format!("$svd_watch_{}", n);
+                let value = value_expr(entry);
+                let ll = if size > 32 { "ll" } else { "" };
+                s += &format!(
+                    "set {v} = {e}\n\
+                     watch {r} if {e} != {v}\n\
+                     commands\n  set {v} = {e}\n  printf \"{p} = %{ll}u\\n\", {v}\nend\n",
+                    v = var,
+                    e = value,
+                    r = register,
+                    p = printf_escape(&entry.path),
+                    ll = ll,
+                );
+            }
+            (Some((offset, width)), _) => {
+                s += &format!(
+                    "# reads can't be limited to bits [{}:{}], whole register is watched\n{} {}\n",
+                    offset + width - 1,
+                    offset,
+                    kind.command(),
+                    register
+                )
+            }
+        }
+    }
+    s
+}
+
+/// GDB command name: `svd_` and alias or path in lower case, other characters are
+/// replaced with `_`. Prefix keeps aliases like `next` or `p` from redefining GDB commands
+pub fn command_name(entry: &Entry) -> String {
+    let name: String = entry
+        .alias
+        .as_deref()
+        .unwrap_or(&entry.path)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("svd_{}", name)
+}
+
+/// C type for reading register
+pub fn c_type(entry: &Entry) -> &'static str {
+    match entry.access_size() {
+        8 => "unsigned char",
+        16 => "unsigned short",
+        64 => "unsigned long long",
+        _ => "unsigned int",
+    }
+}
+
+/// GDB expression reading register or field value
+pub fn value_expr(entry: &Entry) -> String {
+    let read = format!("*({} *)0x{:08x}", c_type(entry), entry.address);
+    match entry.bits {
+        Some((offset, width)) => {
+            format!("((({}) >> {}) & 0x{:x})", read, offset, field_mask(width))
+        }
+        None => read,
+    }
+}
+
+fn command(name: &str, entry: &Entry, node: Option<&Node>) -> String {
+    let size = entry.size.unwrap_or(32);
+    let ll = if size > 32 { "ll" } else { "" };
+    let path = printf_escape(&entry.path);
+    let mut s = format!("define {}\n", name);
+    if let Some(ra) = entry.read_action {
+        s += &format!(
+            "  # reading changes device state (readAction {})\n",
+            ra.as_str()
+        );
+    }
+    s += &format!("  set $svd_value = {}\n", value_expr(entry));
+    match entry.bits {
+        None => {
+            let digits = size.div_ceil(4);
+            s += &format!(
+                "  printf \"{} = 0x%0{}{}x\\n\", $svd_value\n",
+                path, digits, ll
+            );
+        }
+        Some(_) => {
+            let values: Vec<(u64, &str)> = node
+                .iter()
+                .flat_map(|n| &n.enum_values)
+                .flat_map(|evs| &evs.values)
+                .filter_map(|ev| Some((ev.value?, ev.name.as_str())))
+                .collect();
+            s += &enum_printf(&path, ll, &values, 1);
+        }
+    }
+    s += "end\n";
+    s += &format!(
+        "document {}\nPrint {} at 0x{:08x}",
+        name, entry.path, entry.address
+    );
+    if let Some((offset, width)) = entry.bits {
+        s += &format!(" [{}:{}]", offset + width - 1, offset);
+    }
+    if let Some(desc) = node
+        .map(|n| n.description.as_str())
+        .filter(|d| !d.is_empty())
+    {
+        s += &format!("\n{}", desc);
+    }
+    if let Some(ra) = entry.read_action {
+        s += &format!(
+            "\nReading changes device state (readAction {})",
+            ra.as_str()
+        );
+    }
+    s += "\nend\n";
+    s
+}
+
+/// Nested `if` printing name of enumerated value, GDB has no `else if`
+fn enum_printf(path: &str, ll: &str, values: &[(u64, &str)], depth: usize) -> String {
+    let indent = "  ".repeat(depth);
+    match values.split_first() {
+        None => format!("{}printf \"{} = %{}u\\n\", $svd_value\n", indent, path, ll),
+        Some(((value, name), rest)) => format!(
+            "{i}if $svd_value == {v}\n{i}  printf \"{p} = {v} ({n})\\n\"\n{i}else\n{r}{i}end\n",
+            i = indent,
+            v = value,
+            p = path,
+            n = printf_escape(name),
+            r = enum_printf(path, ll, rest, depth + 1),
+        ),
+    }
+}
+
+/// Text inside `printf` format string
+fn printf_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+}
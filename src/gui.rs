@@ -0,0 +1,1432 @@
+use gtk::prelude::*;
+use gtk::{
+    glib, pango, Button, CellRendererText, CellRendererToggle, TreeIter, TreeModelFilter, TreePath,
+    TreeStore, TreeView, TreeViewColumn,
+};
+
+use svdselector::{
+    archive, cache,
+    decode::{decode, find_register, parse_assignment},
+    export::{export, Format},
+    gdb::{self, Watch},
+    pack,
+    registers::{parse_number, Entry, LineError, RegistersFile, Version},
+    search::{Mode, Pattern},
+    Device, Kind, Lookup, Node, ReadAction,
+};
+
+use anyhow::{bail, Context};
+
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+    time::Duration,
+};
+
+use std::collections::HashSet;
+
+use crate::cli::Options;
+
+/// Search expands only first matches, expanding thousands of rows is slow
+const MAX_EXPANDED: usize = 200;
+
+/// Store paths (column 9) of rows shown by search. `None` shows all rows
+type Visible = Rc<RefCell<Option<HashSet<String>>>>;
+
+/// SVD file name and path of SVD inside archive
+type SvdName = (String, Option<String>);
+
+/// Widgets and state used when SVD loaded in background is shown
+#[derive(Clone)]
+struct Ui {
+    window: gtk::Window,
+    view: TreeView,
+    /// Open and Pack buttons, disabled while SVD is loaded
+    open_buttons: gtk::Box,
+    progress: gtk::ProgressBar,
+    search_entry: gtk::SearchEntry,
+    search_mode: gtk::ComboBoxText,
+    svd_filename: Rc<RefCell<Option<SvdName>>>,
+    store: Rc<RefCell<Option<TreeStore>>>,
+    device: Rc<RefCell<Option<Device>>>,
+    registers: Rc<RefCell<RegistersFile>>,
+    visible: Visible,
+    /// Registers file, `registers.txt` by default
+    output: PathBuf,
+    use_cache: bool,
+}
+
+pub fn run(options: Options) {
+    if gtk::init().is_err() {
+        println!("Failed to initialize GTK.");
+        return;
+    }
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    let view = TreeView::new();
+    let open_button = Button::with_label("Open");
+    let pack_button = Button::with_label("Pack…");
+    pack_button.set_tooltip_text(Some("Open SVD of device from CMSIS-Pack"));
+    let open_buttons = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    open_buttons.pack_start(&open_button, true, true, 0);
+    open_buttons.pack_start(&pack_button, true, true, 0);
+    let ok_button = Button::with_label("Ok");
+    let apply_button = Button::with_label("Apply");
+    let cancel_button = Button::with_label("Cancel");
+    let format_combo = gtk::ComboBoxText::new();
+    format_combo.append(Some("v2"), "Format v2");
+    format_combo.append(Some("v1"), "Format v1");
+    format_combo.set_active_id(Some(&options.version.unwrap_or_default().to_string()));
+    let export_combo = gtk::ComboBoxText::new();
+    export_combo.append(Some("none"), "No export");
+    export_combo.append(Some("json"), "Export JSON");
+    export_combo.append(Some("toml"), "Export TOML");
+    export_combo.append(Some("gdb"), "GDB script");
+    export_combo.append(Some("openocd"), "OpenOCD script");
+    export_combo.append(Some("pyocd"), "pyOCD script");
+    export_combo.set_active_id(Some("none"));
+    let search_entry = gtk::SearchEntry::new();
+    search_entry.set_hexpand(true);
+    let search_mode = gtk::ComboBoxText::new();
+    search_mode.append(Some("substring"), "Substring");
+    search_mode.append(Some("glob"), "Glob");
+    search_mode.append(Some("regex"), "Regex");
+    search_mode.set_active_id(Some("substring"));
+    let goto_entry = gtk::Entry::new();
+    goto_entry.set_placeholder_text(Some("Go to address"));
+    let decode_entry = gtk::Entry::new();
+    decode_entry.set_placeholder_text(Some(
+        "RCC.CFGR = 0x0040840A, or value of selected register (press Enter)",
+    ));
+    let decode_label = gtk::Label::new(None);
+    decode_label.set_selectable(true);
+    decode_label.set_xalign(0.0);
+    let decode_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    decode_box.pack_start(&decode_entry, false, false, 0);
+    decode_box.pack_start(&decode_label, false, false, 0);
+    let decode_expander = gtk::Expander::new(Some("Decode value"));
+    decode_expander.add(&decode_box);
+    let watch_menu = gtk::Menu::new();
+    let progress = gtk::ProgressBar::new();
+    progress.set_show_text(true);
+    progress.set_no_show_all(true);
+
+    let svd_filename: Rc<RefCell<Option<SvdName>>> = Rc::new(RefCell::new(None));
+    let store: Rc<RefCell<Option<TreeStore>>> = Rc::new(RefCell::new(None));
+    let device: Rc<RefCell<Option<Device>>> = Rc::new(RefCell::new(None));
+    let registers: Rc<RefCell<RegistersFile>> = Rc::new(RefCell::new(RegistersFile::default()));
+    let visible: Visible = Rc::new(RefCell::new(None));
+
+    window.set_title("SVD");
+    window.set_border_width(10);
+
+    let cell_name = CellRendererText::new();
+    let column_name = TreeViewColumn::new();
+    column_name.pack_start(&cell_name, true);
+    column_name.add_attribute(&cell_name, "text", 0);
+    column_name.add_attribute(&cell_name, "style", 11);
+    column_name.set_title("Name");
+    view.append_column(&column_name);
+
+    let cell_in_out = CellRendererToggle::new();
+    let column_in_out = TreeViewColumn::new();
+    column_in_out.pack_start(&cell_in_out, true);
+    column_in_out.add_attribute(&cell_in_out, "active", 1);
+    column_in_out.add_attribute(&cell_in_out, "activatable", 5);
+    column_in_out.set_title("Out?");
+    view.append_column(&column_in_out);
+
+    let cell_alias = CellRendererText::new();
+    let column_alias = TreeViewColumn::new();
+    column_alias.pack_start(&cell_alias, true);
+    column_alias.add_attribute(&cell_alias, "text", 4);
+    column_alias.add_attribute(&cell_alias, "editable", 5);
+    column_alias.set_title("Alias");
+    view.append_column(&column_alias);
+
+    let cell_address = CellRendererText::new();
+    let column_address = TreeViewColumn::new();
+    column_address.pack_start(&cell_address, true);
+    column_address.add_attribute(&cell_address, "text", 2);
+    column_address.set_title("Address");
+    view.append_column(&column_address);
+
+    for (title, ncol) in &[
+        ("Size", 12),
+        ("Access", 13),
+        ("Reset", 14),
+        ("Mask", 15),
+        ("Side effects", 17),
+    ] {
+        let cell = CellRendererText::new();
+        let column = TreeViewColumn::new();
+        column.pack_start(&cell, true);
+        column.add_attribute(&cell, "text", *ncol);
+        column.set_title(title);
+        view.append_column(&column);
+    }
+
+    let cell_description = CellRendererText::new();
+    let column_description = TreeViewColumn::new();
+    column_description.pack_start(&cell_description, true);
+    column_description.add_attribute(&cell_description, "text", 3);
+    column_description.set_title("Description");
+    view.append_column(&column_description);
+
+    let scrolled_window = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+    scrolled_window.set_policy(gtk::PolicyType::Always, gtk::PolicyType::Always);
+    //scrolled_window.add_with_viewport(&view);
+    scrolled_window.add(&view);
+
+    scrolled_window.set_size_request(500, 500);
+    scrolled_window.set_hexpand(true);
+    scrolled_window.set_vexpand(true);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(5);
+
+    view.set_tooltip_column(8);
+
+    grid.attach(&open_buttons, 0, 0, 1, 1);
+    grid.attach(&search_entry, 1, 0, 2, 1);
+    grid.attach(&search_mode, 3, 0, 1, 1);
+    grid.attach(&goto_entry, 4, 0, 1, 1);
+    grid.attach(&scrolled_window, 0, 1, 5, 1);
+    grid.attach(&decode_expander, 0, 2, 5, 1);
+    grid.attach(&format_combo, 0, 3, 1, 1);
+    grid.attach(&export_combo, 1, 3, 1, 1);
+    grid.attach(&ok_button, 2, 3, 1, 1);
+    grid.attach(&apply_button, 3, 3, 1, 1);
+    grid.attach(&cancel_button, 4, 3, 1, 1);
+    grid.attach(&progress, 0, 4, 5, 1);
+
+    window.add(&grid);
+
+    window.show_all();
+
+    let ui = Ui {
+        window: window.clone(),
+        view: view.clone(),
+        open_buttons,
+        progress,
+        search_entry: search_entry.clone(),
+        search_mode: search_mode.clone(),
+        svd_filename: svd_filename.clone(),
+        store: store.clone(),
+        device: device.clone(),
+        registers: registers.clone(),
+        visible: visible.clone(),
+        output: options.output.clone(),
+        use_cache: options.use_cache,
+    };
+    let output = &options.output;
+    {
+        let mut regs = registers.borrow_mut();
+        if output.exists() {
+            match RegistersFile::read(output) {
+                Ok((file, errors)) => {
+                    *regs = file;
+                    if options.drop_invalid
+                        || (!errors.is_empty() && !keep_invalid_lines(&window, output, &errors))
+                    {
+                        regs.remove_invalid();
+                    }
+                }
+                Err(e) => show_error(&window, &format!("Unable to read {}", output.display()), &e),
+            }
+        }
+        if let Err(e) = options.apply_svd(&mut regs) {
+            show_error(&window, "Unable to find SVD", &e);
+        }
+        if !regs.svd.is_empty() {
+            println!("SVD File {}", regs.svd);
+            load_svd(&ui, PathBuf::from(&regs.svd), regs.svd_inner.clone());
+        } else {
+            choose_and_load(&ui);
+        }
+    }
+
+    window.connect_delete_event(|_, _| {
+        gtk::main_quit();
+        Inhibit(false)
+    });
+
+    cancel_button.connect_clicked(|_| {
+        gtk::main_quit();
+    });
+
+    {
+        let store = store.clone();
+        let device = device.clone();
+        view.connect_row_expanded(move |view, _, path| {
+            if let (Some(st), Some(d), Some(path)) =
+                (&*store.borrow(), &*device.borrow(), store_path(view, path))
+            {
+                populate(st, &st.iter(&path).unwrap(), d);
+            }
+        });
+    }
+    {
+        let window = window.clone();
+        let view = view.clone();
+        let store = store.clone();
+        cell_in_out.connect_toggled(move |_, path| {
+            // Confirmation dialog runs nested main loop, which can show newly loaded SVD,
+            // so store must not stay borrowed
+            let st = store.borrow().clone();
+            if let (Some(st), Some(path)) = (st, store_path(&view, &path)) {
+                on_toggle(&window, &st, &path)
+            }
+        });
+    }
+    {
+        let view = view.clone();
+        let store = store.clone();
+        cell_alias.connect_edited(move |_, path, new_text| {
+            if let (Some(st), Some(path)) = (&*store.borrow(), store_path(&view, &path)) {
+                let iter = st.iter(&path).unwrap();
+                st.set_value(&iter, 4, &new_text.to_value());
+            }
+        });
+    }
+    {
+        let view = view.clone();
+        let store = store.clone();
+        let device = device.clone();
+        let visible = visible.clone();
+        let search_mode = search_mode.clone();
+        search_entry.connect_search_changed(move |entry| {
+            if let (Some(st), Some(d)) = (&*store.borrow(), &*device.borrow()) {
+                apply_search(&view, st, d, &visible, entry, &search_mode);
+            }
+        });
+    }
+    {
+        let view = view.clone();
+        let store = store.clone();
+        let device = device.clone();
+        let visible = visible.clone();
+        let search_entry = search_entry.clone();
+        search_mode.connect_changed(move |mode| {
+            if let (Some(st), Some(d)) = (&*store.borrow(), &*device.borrow()) {
+                apply_search(&view, st, d, &visible, &search_entry, mode);
+            }
+        });
+    }
+    {
+        let window = window.clone();
+        let view = view.clone();
+        let store = store.clone();
+        let device = device.clone();
+        let visible = visible.clone();
+        let search_entry = search_entry.clone();
+        goto_entry.connect_activate(move |entry| {
+            let message = match (&*store.borrow(), &*device.borrow()) {
+                (Some(st), Some(d)) => go_to_address(&view, st, d, &visible, &search_entry, entry),
+                _ => None,
+            };
+            if let Some((title, message)) = message {
+                show_info(&window, &title, &message);
+            }
+        });
+    }
+    {
+        let view = view.clone();
+        let device = device.clone();
+        decode_entry.connect_activate(move |entry| {
+            if let Some(d) = &*device.borrow() {
+                decode_value(&view, d, entry, &decode_label);
+            }
+        });
+    }
+    {
+        let window = window.clone();
+        let output = output.clone();
+        let store = store.clone();
+        let svd_filename = svd_filename.clone();
+        let registers = registers.clone();
+        let device = device.clone();
+        let format_combo = format_combo.clone();
+        let export_combo = export_combo.clone();
+        ok_button.connect_clicked(move |_| {
+            let result = match (&*store.borrow(), &*svd_filename.borrow()) {
+                (Some(st), Some(svd_file)) => {
+                    let version = selected_version(&format_combo);
+                    let device = device.borrow();
+                    let export = selected_export(&export_combo).zip(device.as_ref());
+                    let file = &mut registers.borrow_mut();
+                    save_data(st, &output, svd_file, file, version, export)
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                let title = format!("Unable to save {}", output.display());
+                show_error(&window, &title, &e);
+                return;
+            }
+            gtk::main_quit();
+        });
+    }
+    {
+        let window = window.clone();
+        let output = output.clone();
+        let store = store.clone();
+        let svd_filename = svd_filename.clone();
+        let registers = registers.clone();
+        let device = device.clone();
+        let format_combo = format_combo.clone();
+        let export_combo = export_combo.clone();
+        apply_button.connect_clicked(move |_| {
+            let result = match (&*store.borrow(), &*svd_filename.borrow()) {
+                (Some(st), Some(svd_file)) => {
+                    let version = selected_version(&format_combo);
+                    let device = device.borrow();
+                    let export = selected_export(&export_combo).zip(device.as_ref());
+                    let file = &mut registers.borrow_mut();
+                    save_data(st, &output, svd_file, file, version, export)
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                let title = format!("Unable to save {}", output.display());
+                show_error(&window, &title, &e);
+            }
+        });
+    }
+
+    for (label, kind) in [
+        ("Watch writes of ticked…", Watch::Write),
+        ("Watch reads of ticked…", Watch::Read),
+        ("Watch accesses of ticked…", Watch::Access),
+    ] {
+        let item = gtk::MenuItem::with_label(label);
+        watch_menu.append(&item);
+        let window = window.clone();
+        let output = output.clone();
+        let store = store.clone();
+        let svd_filename = svd_filename.clone();
+        let registers = registers.clone();
+        item.connect_activate(move |_| {
+            let st = store.borrow().clone();
+            let svd_file = svd_filename.borrow().clone();
+            if let (Some(st), Some(svd_file)) = (st, svd_file) {
+                let file = registers.borrow().clone();
+                if let Err(e) = save_watchpoints(&window, &st, &output, &svd_file, file, kind) {
+                    show_error(&window, "Unable to save watchpoints", &e);
+                }
+            }
+        });
+    }
+    watch_menu.show_all();
+    view.connect_button_press_event(move |_, event| {
+        if event.button() == 3 {
+            watch_menu.popup_easy(event.button(), event.time());
+            Inhibit(true)
+        } else {
+            Inhibit(false)
+        }
+    });
+    {
+        let ui = ui.clone();
+        pack_button.connect_clicked(move |_| {
+            if let Some(d) = choose_pack_device(&ui.window) {
+                println!("Open SVD of {} from {:?}", d.device, d.path);
+                load_svd(&ui, d.path, d.inner);
+            }
+        });
+    }
+    open_button.connect_clicked(move |_| choose_and_load(&ui));
+    gtk::main();
+}
+
+/// Show store in view through filter which hides rows not found by search
+fn set_model(view: &TreeView, store: &TreeStore, visible: &Visible) {
+    let filter = TreeModelFilter::new(store, None);
+    let visible = visible.clone();
+    filter.set_visible_func(move |model, iter| match &*visible.borrow() {
+        Some(paths) => {
+            let path = model.value(iter, 9).get::<String>().unwrap_or_default();
+            // Placeholder rows (empty path) keep expanders of not populated rows
+            path.is_empty() || paths.contains(&path)
+        }
+        None => true,
+    });
+    view.set_model(Some(&filter));
+}
+
+/// Path in store of row shown in view
+fn store_path(view: &TreeView, path: &TreePath) -> Option<TreePath> {
+    match view.model()?.downcast::<TreeModelFilter>() {
+        Ok(filter) => filter.convert_path_to_child_path(path),
+        Err(_) => Some(path.clone()),
+    }
+}
+
+/// Path in view of store row. `None` if row is hidden by search
+fn view_path(view: &TreeView, path: &TreePath) -> Option<TreePath> {
+    match view.model()?.downcast::<TreeModelFilter>() {
+        Ok(filter) => filter.convert_child_path_to_path(path),
+        Err(_) => Some(path.clone()),
+    }
+}
+
+/// Show only rows matching search text with their ancestors and descendants
+/// and expand tree to matches. Tick state of hidden rows is kept in store
+fn apply_search(
+    view: &TreeView,
+    store: &TreeStore,
+    device: &Device,
+    visible: &Visible,
+    entry: &gtk::SearchEntry,
+    mode: &gtk::ComboBoxText,
+) {
+    let text = entry.text();
+    let style = entry.style_context();
+    style.remove_class("error");
+    entry.set_tooltip_text(None);
+    let mode = mode
+        .active_id()
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(Mode::Substring);
+    let pattern = match Pattern::new(&text, mode) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            style.add_class("error");
+            entry.set_tooltip_text(Some(&e.to_string()));
+            return;
+        }
+    };
+    let mut matched = Vec::new();
+    *visible.borrow_mut() = if text.is_empty() {
+        None
+    } else {
+        let mut paths = HashSet::new();
+        search_nodes(
+            &device.peripherals,
+            &pattern,
+            false,
+            &mut paths,
+            &mut matched,
+        );
+        Some(paths)
+    };
+    if let Some(filter) = view
+        .model()
+        .and_then(|m| m.downcast::<TreeModelFilter>().ok())
+    {
+        filter.refilter();
+    }
+    for path in matched.into_iter().take(MAX_EXPANDED) {
+        if let Some(iter) = ensure_row(store, device, path) {
+            let mut path = store.path(&iter).unwrap();
+            if path.up() && path.depth() > 0 {
+                if let Some(path) = view_path(view, &path) {
+                    view.expand_to_path(&path);
+                }
+            }
+        }
+    }
+}
+
+/// Collect paths of nodes which match or have matching ancestor or descendant.
+/// Searches device, not store, as rows of collapsed nodes are not created yet.
+/// Returns `true` if any node matches
+fn search_nodes<'a>(
+    nodes: &'a [Node],
+    pattern: &Pattern,
+    parent_matches: bool,
+    paths: &mut HashSet<String>,
+    matched: &mut Vec<&'a str>,
+) -> bool {
+    let mut found = false;
+    for node in nodes {
+        let address = node
+            .address
+            .map(|a| format!("0x{:08x}", a))
+            .unwrap_or_default();
+        let is_match = pattern.matches(&node.path, &node.description, &address);
+        if is_match {
+            matched.push(&node.path);
+        }
+        let child_found = search_nodes(
+            &node.children,
+            pattern,
+            parent_matches || is_match,
+            paths,
+            matched,
+        );
+        if parent_matches || is_match || child_found {
+            paths.insert(node.path.clone());
+        }
+        found |= is_match || child_found;
+    }
+    found
+}
+
+/// Select register containing address typed in `entry`. If there is no such register,
+/// select peripheral and show its address block
+fn go_to_address(
+    view: &TreeView,
+    store: &TreeStore,
+    device: &Device,
+    visible: &Visible,
+    search_entry: &gtk::SearchEntry,
+    entry: &gtk::Entry,
+) -> Option<(String, String)> {
+    let style = entry.style_context();
+    style.remove_class("error");
+    entry.set_tooltip_text(None);
+    let address = match parse_number(entry.text().trim()) {
+        Some(address) => address,
+        None => {
+            style.add_class("error");
+            entry.set_tooltip_text(Some("Invalid address"));
+            return None;
+        }
+    };
+    let (node, message) = match device.lookup(address) {
+        Some(Lookup::Register { register, fields }) => {
+            let range = register.address_range().unwrap();
+            let names: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+            entry.set_tooltip_text(Some(&format!(
+                "{} byte {}: {}",
+                register.path,
+                address - range.start,
+                names.join(", ")
+            )));
+            (register, None)
+        }
+        Some(Lookup::Block { peripheral, block }) => (
+            peripheral,
+            Some(format!(
+                "{} address block 0x{:08x}..0x{:08x}",
+                peripheral.name, block.start, block.end
+            )),
+        ),
+        None => {
+            style.add_class("error");
+            entry.set_tooltip_text(Some(&format!("0x{:08x} is not in any peripheral", address)));
+            return None;
+        }
+    };
+    let iter = ensure_row(store, device, &node.path)?;
+    let store_path = store.path(&iter).unwrap();
+    if view_path(view, &store_path).is_none() {
+        // Row is hidden by search
+        search_entry.set_text("");
+        *visible.borrow_mut() = None;
+        if let Some(filter) = view
+            .model()
+            .and_then(|m| m.downcast::<TreeModelFilter>().ok())
+        {
+            filter.refilter();
+        }
+    }
+    if let Some(path) = view_path(view, &store_path) {
+        view.expand_to_path(&path);
+        view.selection().select_path(&path);
+        view.scroll_to_cell(Some(&path), None::<&TreeViewColumn>, true, 0.5, 0.);
+    }
+    message.map(|m| (format!("No register at 0x{:08x}", address), m))
+}
+
+/// Show fields of `PATH = VALUE` typed in entry. Register selected in tree (or register
+/// of selected field) is used if only value is typed
+fn decode_value(view: &TreeView, device: &Device, entry: &gtk::Entry, label: &gtk::Label) {
+    let text = entry.text();
+    let selected = || {
+        let (model, iter) = view.selection().selected()?;
+        let mut path = model.value(&iter, 9).get::<String>().ok()?;
+        loop {
+            if let Some(register) = find_register(device, &path) {
+                return Some(register);
+            }
+            path.truncate(path.rfind('.')?);
+        }
+    };
+    let decoded = match parse_assignment(&text) {
+        Some((path, value)) => find_register(device, path)
+            .map(|r| decode(r, value))
+            .ok_or_else(|| format!("Register {} not found", path)),
+        None => match (parse_number(text.trim()), selected()) {
+            (Some(value), Some(register)) => Ok(decode(register, value)),
+            (Some(_), None) => Err("Select register or type PATH = VALUE".to_string()),
+            (None, _) => Err("Expected PATH = VALUE or value".to_string()),
+        },
+    };
+    match decoded {
+        Ok(decoded) => {
+            entry.set_tooltip_text(None);
+            label.set_markup(&format!(
+                "<tt>{}</tt>",
+                glib::markup_escape_text(decoded.to_string().trim_end())
+            ));
+        }
+        Err(e) => {
+            entry.set_tooltip_text(Some(&e));
+            label.set_text(&e);
+        }
+    }
+}
+
+/// Row of node with SVD path. Rows of its ancestors are populated if needed
+fn ensure_row(store: &TreeStore, device: &Device, path: &str) -> Option<TreeIter> {
+    let mut chain = Vec::new();
+    if !node_chain(&device.peripherals, path, &mut chain) {
+        return None;
+    }
+    let mut parent: Option<TreeIter> = None;
+    for node in chain {
+        if let Some(parent) = &parent {
+            populate(store, parent, device);
+        }
+        let iter = store.iter_children(parent.as_ref())?;
+        while get_reg_path(store, &iter) != node.path {
+            if !store.iter_next(&iter) {
+                return None;
+            }
+        }
+        parent = Some(iter);
+    }
+    parent
+}
+
+/// Nodes from peripheral down to node with SVD path. Array elements have paths
+/// in namespace of array parent, so path prefix can't be used to find ancestors
+fn node_chain<'a>(nodes: &'a [Node], path: &str, chain: &mut Vec<&'a Node>) -> bool {
+    for node in nodes {
+        chain.push(node);
+        if node.path == path || node_chain(&node.children, path, chain) {
+            return true;
+        }
+        chain.pop();
+    }
+    false
+}
+
+fn choose_file(window: &gtk::Window) -> Option<PathBuf> {
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some("Please choose a file"),
+        Some(window),
+        gtk::FileChooserAction::Open,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Open", gtk::ResponseType::Ok),
+        ],
+    );
+    let response = dialog.run();
+    let pathbuf = match response {
+        gtk::ResponseType::Ok => dialog.filename(),
+        _ => None,
+    };
+    dialog.close();
+    pathbuf
+}
+
+/// Ask SVD file until it is loaded successfully or user cancels
+fn choose_and_load(ui: &Ui) {
+    if let Some(pathbuf) = choose_file(&ui.window) {
+        println!("Open SVD File {:?}", pathbuf);
+        load_svd(ui, pathbuf, None);
+    }
+}
+
+/// Ask directory with CMSIS-Packs and device name. Returns SVD of device
+fn choose_pack_device(window: &gtk::Window) -> Option<pack::DeviceSvd> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Open SVD of device from CMSIS-Pack"),
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Open", gtk::ResponseType::Ok),
+        ],
+    );
+    let folder = gtk::FileChooserButton::new("Packs", gtk::FileChooserAction::SelectFolder);
+    let name = gtk::Entry::new();
+    name.set_placeholder_text(Some("STM32F407VG"));
+    // Device names of chosen packs are suggested while typing
+    let names = gtk::ListStore::new(&[String::static_type()]);
+    let completion = gtk::EntryCompletion::new();
+    completion.set_model(Some(&names));
+    completion.set_text_column(0);
+    completion.set_minimum_key_length(2);
+    name.set_completion(Some(&completion));
+    folder.connect_selection_changed(move |folder| {
+        names.clear();
+        // Packs which can't be read are reported when device is opened
+        if let Some(Ok((devices, _))) = folder.filename().map(|dir| pack::devices(&dir)) {
+            let mut devices: Vec<String> = devices.into_iter().map(|d| d.device).collect();
+            devices.sort();
+            devices.dedup();
+            for d in &devices {
+                names.insert_with_values(None, &[(0, d)]);
+            }
+        }
+    });
+    {
+        let dialog = dialog.clone();
+        name.connect_activate(move |_| dialog.response(gtk::ResponseType::Ok));
+    }
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(5);
+    grid.set_column_spacing(5);
+    grid.attach(&gtk::Label::new(Some("Packs directory")), 0, 0, 1, 1);
+    grid.attach(&folder, 1, 0, 1, 1);
+    grid.attach(&gtk::Label::new(Some("Device")), 0, 1, 1, 1);
+    grid.attach(&name, 1, 1, 1, 1);
+    dialog.content_area().pack_start(&grid, true, true, 5);
+    dialog.show_all();
+    let result = loop {
+        if dialog.run() != gtk::ResponseType::Ok {
+            break None;
+        }
+        let device = name.text();
+        if let (Some(dir), false) = (folder.filename(), device.is_empty()) {
+            let found = pack::devices(&dir).and_then(|(devices, errors)| {
+                show_pack_errors(dialog.upcast_ref(), &errors);
+                pack::find_device(&devices, &dir, &device)
+            });
+            match found {
+                Ok(d) => break Some(d),
+                Err(e) => show_error(dialog.upcast_ref(), "Device not found", &e),
+            }
+        }
+    };
+    dialog.close();
+    result
+}
+
+/// Show packs which can't be read
+fn show_pack_errors(window: &gtk::Window, errors: &[anyhow::Error]) {
+    if errors.is_empty() {
+        return;
+    }
+    let lines: Vec<String> = errors.iter().map(|e| format!("{:#}", e)).collect();
+    eprintln!("Packs not read:\n{}", lines.join("\n"));
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::Ok,
+        &format!("{} packs can't be read", errors.len()),
+    );
+    dialog.set_secondary_text(Some(&lines.join("\n")));
+    dialog.run();
+    dialog.close();
+}
+
+/// Path of SVD inside archive, asked if there are several. `Some(None)` for files
+/// which are not archives, `None` if user cancels
+fn choose_inner(window: &gtk::Window, path: &Path) -> Option<Option<String>> {
+    if !archive::is_archive(path) {
+        return Some(None);
+    }
+    let mut entries = match archive::svd_entries(path) {
+        Ok(entries) if !entries.is_empty() => entries,
+        Ok(_) => {
+            let e = anyhow::anyhow!("{} doesn't contain SVD files", path.display());
+            show_error(window, "Unable to open archive", &e);
+            return None;
+        }
+        Err(e) => {
+            show_error(window, "Unable to open archive", &e);
+            return None;
+        }
+    };
+    if entries.len() == 1 {
+        return Some(entries.pop());
+    }
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Choose SVD in archive"),
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Open", gtk::ResponseType::Ok),
+        ],
+    );
+    let list = gtk::ListBox::new();
+    for entry in &entries {
+        let label = gtk::Label::new(Some(entry));
+        label.set_xalign(0.);
+        list.add(&label);
+    }
+    list.select_row(list.row_at_index(0).as_ref());
+    {
+        let dialog = dialog.clone();
+        list.connect_row_activated(move |_, _| dialog.response(gtk::ResponseType::Ok));
+    }
+    let scrolled_window = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+    scrolled_window.set_size_request(400, 300);
+    scrolled_window.add(&list);
+    dialog
+        .content_area()
+        .pack_start(&scrolled_window, true, true, 0);
+    dialog.show_all();
+    let response = dialog.run();
+    let selected = list.selected_row().map(|row| row.index() as usize);
+    dialog.close();
+    match (response, selected) {
+        (gtk::ResponseType::Ok, Some(i)) => Some(entries.get(i).cloned()),
+        _ => None,
+    }
+}
+
+/// Show informational message
+fn show_info(window: &gtk::Window, title: &str, message: &str) {
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Info,
+        gtk::ButtonsType::Ok,
+        title,
+    );
+    dialog.set_secondary_text(Some(message));
+    dialog.run();
+    dialog.close();
+}
+
+/// Show error message with all its causes
+fn show_error(window: &gtk::Window, title: &str, e: &anyhow::Error) {
+    eprintln!("{}: {:#}", title, e);
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Ok,
+        title,
+    );
+    let causes: Vec<String> = e.chain().map(|c| c.to_string()).collect();
+    dialog.set_secondary_text(Some(&causes.join("\n")));
+    dialog.run();
+    dialog.close();
+}
+
+/// Ask whether lines of registers file which can't be parsed should be kept in it
+fn keep_invalid_lines(window: &gtk::Window, output: &Path, errors: &[LineError]) -> bool {
+    for e in errors {
+        eprintln!("{} {}", output.display(), e);
+    }
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::None,
+        &format!(
+            "{} lines of {} can't be parsed",
+            errors.len(),
+            output.display()
+        ),
+    );
+    let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    dialog.set_secondary_text(Some(&format!(
+        "{}\n\nKeep them in {} or remove on save?",
+        lines.join("\n"),
+        output.display()
+    )));
+    dialog.add_buttons(&[
+        ("Remove", gtk::ResponseType::Reject),
+        ("Keep", gtk::ResponseType::Accept),
+    ]);
+    let response = dialog.run();
+    dialog.close();
+    response != gtk::ResponseType::Reject
+}
+
+/// Show entries of registers file which are absent in SVD
+fn show_unmatched(window: &gtk::Window, output: &Path, unmatched: &[Entry]) {
+    if unmatched.is_empty() {
+        return;
+    }
+    let lines: Vec<String> = unmatched.iter().map(|e| e.to_string()).collect();
+    eprintln!("Not found in SVD:\n{}", lines.join("\n"));
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::Ok,
+        &format!(
+            "{} entries of {} not found in SVD",
+            unmatched.len(),
+            output.display()
+        ),
+    );
+    dialog.set_secondary_text(Some(&lines.join("\n")));
+    dialog.run();
+    dialog.close();
+}
+
+/// Parse SVD in background thread with progress bar, then show its tree.
+/// SVD inside archive is asked if `inner` is not set. Another file is asked if it can't be loaded
+fn load_svd(ui: &Ui, svd_path: PathBuf, inner: Option<String>) {
+    let inner = match inner {
+        Some(inner) => Some(inner),
+        None => match choose_inner(&ui.window, &svd_path) {
+            Some(inner) => inner,
+            None => return,
+        },
+    };
+    let name = archive::display_name(&svd_path, inner.as_deref());
+    ui.open_buttons.set_sensitive(false);
+    ui.progress.set_text(Some(&format!("Loading {}", name)));
+    ui.progress.show();
+    {
+        let progress = ui.progress.clone();
+        glib::timeout_add_local(Duration::from_millis(100), move || {
+            progress.pulse();
+            glib::Continue(progress.is_visible())
+        });
+    }
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    {
+        let svd_path = svd_path.clone();
+        let inner = inner.clone();
+        let use_cache = ui.use_cache;
+        thread::spawn(move || {
+            let inner = inner.as_deref();
+            sender.send(if use_cache {
+                // Device is usable without cache, so failed write is only printed
+                cache::load(&svd_path, inner).map(|(device, error)| {
+                    if let Some(e) = error {
+                        eprintln!("Warning: {:#}", e);
+                    }
+                    device
+                })
+            } else {
+                Device::load(&svd_path, inner)
+            })
+        });
+    }
+    let ui = ui.clone();
+    receiver.attach(None, move |result| {
+        ui.progress.hide();
+        ui.open_buttons.set_sensitive(true);
+        match result {
+            Ok(device) => show_device(&ui, &svd_path, inner.clone(), device),
+            Err(e) => {
+                show_error(&ui.window, &format!("Unable to load {}", name), &e);
+                choose_and_load(&ui);
+            }
+        }
+        glib::Continue(false)
+    });
+}
+
+/// Show tree of loaded device and tick items from `registers.txt`
+fn show_device(ui: &Ui, svd_path: &Path, inner: Option<String>, device: Device) {
+    let st = new_store();
+    for p in &device.peripherals {
+        add_node(&st, None, p, false);
+    }
+    // Expanding rows below populates them, so store and device are needed in handler
+    *ui.store.borrow_mut() = Some(st.clone());
+    *ui.device.borrow_mut() = Some(device);
+    let unmatched: Vec<Entry> = {
+        let device = ui.device.borrow();
+        let device = device.as_ref().unwrap();
+        set_model(&ui.view, &st, &ui.visible);
+        apply_search(
+            &ui.view,
+            &st,
+            device,
+            &ui.visible,
+            &ui.search_entry,
+            &ui.search_mode,
+        );
+        let registers = ui.registers.borrow();
+        select_items(&ui.view, &st, device, &registers)
+            .into_iter()
+            .cloned()
+            .collect()
+    };
+    ui.window
+        .set_title(&archive::display_name(svd_path, inner.as_deref()));
+    *ui.svd_filename.borrow_mut() = svd_path.to_str().map(|path| (path.to_string(), inner));
+    // Dialog is shown when nothing is borrowed, handlers can run in its main loop
+    show_unmatched(&ui.window, &ui.output, &unmatched);
+}
+
+fn new_store() -> TreeStore {
+    TreeStore::new(&[
+        String::static_type(),       // name
+        bool::static_type(),         // active
+        String::static_type(),       // address
+        String::static_type(),       // description
+        String::static_type(),       // alias
+        bool::static_type(),         // sens
+        String::static_type(),       // offset
+        String::static_type(),       // width
+        String::static_type(),       // tooltip
+        String::static_type(),       // path
+        String::static_type(),       // type
+        pango::Style::static_type(), // style
+        String::static_type(),       // size
+        String::static_type(),       // access
+        String::static_type(),       // reset value
+        String::static_type(),       // reset mask
+        String::static_type(),       // read action
+        String::static_type(),       // side effects
+    ])
+}
+
+/// Add row of node. Rows of its children are added by [`populate`] when it is expanded,
+/// until then it has one empty placeholder child
+fn add_node(store: &TreeStore, parent: Option<&TreeIter>, node: &Node, in_array: bool) {
+    let iter = store.append(parent);
+    let desc = &node.description;
+    store.set(
+        &iter,
+        &[
+            (0, &node.name),
+            (5, &node.kind.is_selectable()),
+            (9, &node.path),
+            (10, &node.kind.as_str()),
+        ],
+    );
+    if let Some(addr) = node.address {
+        store.set_value(&iter, 2, &format!("0x{:08x}", addr).to_value());
+    }
+    if !matches!(
+        node.kind,
+        Kind::Peripheral | Kind::Cluster | Kind::ClusterArray
+    ) {
+        let props = &node.properties;
+        // Reset value of field has width of field
+        let digits = match node.bits {
+            Some((_, width)) => width.div_ceil(4),
+            None => props.size.unwrap_or(32).div_ceil(4),
+        } as usize;
+        if let Some(size) = props.size {
+            store.set_value(&iter, 12, &size.to_string().to_value());
+        }
+        if let Some(access) = props.access {
+            store.set_value(&iter, 13, &access.as_str().to_value());
+        }
+        if let Some(value) = props.reset_value {
+            let value = format!("0x{:01$x}", value, digits);
+            store.set_value(&iter, 14, &value.to_value());
+        }
+        if let Some(mask) = props.reset_mask {
+            let mask = format!("0x{:01$x}", mask, digits);
+            store.set_value(&iter, 15, &mask.to_value());
+        }
+    }
+    let mut effects = Vec::new();
+    if !matches!(
+        node.kind,
+        Kind::Peripheral | Kind::Cluster | Kind::ClusterArray
+    ) {
+        if let Some(ra) = node.read_side_effect() {
+            store.set_value(&iter, 16, &ra.as_str().to_value());
+            effects.push(format!("read: {}", ra.as_str()));
+        }
+        if let Some(mwv) = node.write_side_effect() {
+            effects.push(format!("write: {}", mwv.as_str()));
+        }
+    }
+    let effects = effects.join(", ");
+    if !effects.is_empty() {
+        store.set_value(&iter, 17, &format!("\u{26a0} {}", effects).to_value());
+    }
+    let derived = match &node.derived_from {
+        Some(base) => {
+            store.set_value(&iter, 11, &pango::Style::Italic.to_value());
+            format!("\n  derived from: <i>{}</i>", base)
+        }
+        None => String::new(),
+    };
+    let tooltip = match node.kind {
+        Kind::Peripheral => format!("<b>{}</b>{}\n{}", &node.name, derived, desc),
+        Kind::Cluster | Kind::Register => format!(
+            "<b>{}{}</b>{}\n  offset: 0x{:02x}\n{}",
+            &node.path,
+            match (in_array, node.kind) {
+                (true, _) => "",
+                (false, Kind::Cluster) => " cluster ",
+                _ => " register ",
+            },
+            derived,
+            node.offset,
+            desc
+        ),
+        Kind::ClusterArray => format!("<b>{} cluster array</b>{}\n{}", &node.path, derived, desc),
+        Kind::RegisterArray => format!("<b>{} register array</b>{}\n{}", &node.path, derived, desc),
+        Kind::FieldArray => format!("<b>{} field array</b>{}\n{}", &node.path, derived, desc),
+        Kind::Field => {
+            let (offset, width) = node.bits.unwrap_or_default();
+            let mut svalues = String::new();
+            for evalues in &node.enum_values {
+                if let Some(evs_name) = &evalues.derived_from {
+                    svalues.push_str(&format!("\n derived from: <i>{}</i>", evs_name));
+                }
+                for ev in &evalues.values {
+                    if let Some(val) = ev.value {
+                        svalues.push_str(&format!("\n\t{} : {}", val, ev.name));
+                    }
+                }
+            }
+            store.set(
+                &iter,
+                &[
+                    (3, &format!("[{}-{}]: {}", offset + width - 1, offset, desc)),
+                    (6, &offset.to_string()),
+                    (7, &width.to_string()),
+                ],
+            );
+            format!(
+                "<b>{} field</b>{}\n [{}-{}]: {}{}{}",
+                &node.path,
+                derived,
+                offset + width - 1,
+                offset,
+                desc,
+                (if !svalues.is_empty() { "\nValues:" } else { "" }),
+                &svalues
+            )
+        }
+    };
+    if node.kind != Kind::Field {
+        store.set_value(&iter, 3, &desc.to_value());
+    }
+    let tooltip = if !effects.is_empty() {
+        format!("{}\n<b>Side effects:</b> {}", tooltip, effects)
+    } else {
+        tooltip
+    };
+    store.set_value(&iter, 8, &tooltip.to_value());
+
+    if !node.children.is_empty() {
+        store.append(Some(&iter));
+    }
+}
+
+/// Replace placeholder child of row with rows of node children
+fn populate(store: &TreeStore, iter: &TreeIter, device: &Device) {
+    let placeholder = match store.iter_children(Some(iter)) {
+        Some(child) if get_reg_path(store, &child).is_empty() => child,
+        _ => return,
+    };
+    if let Some(node) = device.find(&get_reg_path(store, iter)) {
+        let in_array = matches!(
+            node.kind,
+            Kind::ClusterArray | Kind::RegisterArray | Kind::FieldArray
+        );
+        // Children are added before placeholder is removed, so expanded row stays expanded
+        for child in &node.children {
+            add_node(store, Some(iter), child, in_array);
+        }
+    }
+    store.remove(&placeholder);
+}
+
+/// Tick registers and fields from `registers.txt`. Returns entries not found in tree
+fn select_items<'a>(
+    view: &TreeView,
+    store: &TreeStore,
+    device: &Device,
+    file: &'a RegistersFile,
+) -> Vec<&'a Entry> {
+    let mut unmatched = Vec::new();
+    for entry in file.entries() {
+        match ensure_row(store, device, &entry.path) {
+            Some(iter) if store.get_bool(&iter, 5) => {
+                store.set_value(&iter, 1, &true.to_value());
+                if let Some(alias) = &entry.alias {
+                    store.set_value(&iter, 4, &alias.to_value());
+                }
+                if let Some(path) = view_path(view, &store.path(&iter).unwrap()) {
+                    view.expand_to_path(&path);
+                }
+            }
+            _ => unmatched.push(entry),
+        }
+    }
+    unmatched
+}
+
+fn get_reg_path(store: &TreeStore, citer: &TreeIter) -> String {
+    store.get_string(citer, 9)
+}
+
+/// Entry of ticked register or field
+fn get_entry(store: &TreeStore, iter: &TreeIter) -> Option<Entry> {
+    let bits = match store.get_string(iter, 10).as_str() {
+        "r" => None,
+        "f" => Some((
+            store.get_string(iter, 6).parse().unwrap_or_default(),
+            store.get_string(iter, 7).parse().unwrap_or_default(),
+        )),
+        _ => return None,
+    };
+    let alias = store.get_string(iter, 4);
+    Some(Entry {
+        path: get_reg_path(store, iter),
+        alias: Some(alias).filter(|a| !a.is_empty()),
+        address: parse_number(&store.get_string(iter, 2)).unwrap_or_default(),
+        bits,
+        size: store.get_string(iter, 12).parse().ok(),
+        read_action: ReadAction::parse_str(&store.get_string(iter, 16)),
+    })
+}
+
+fn recursive_save(store: &TreeStore, iter: &TreeIter, file: &mut RegistersFile) {
+    if let Some(iter) = &store.iter_children(Some(iter)) {
+        loop {
+            if let Some(entry) = get_entry(store, iter) {
+                if store.get_bool(iter, 1) {
+                    file.insert(entry);
+                } else {
+                    file.remove(&entry.path);
+                }
+            }
+            recursive_save(store, iter, file);
+            if !store.iter_next(iter) {
+                break;
+            }
+        }
+    }
+}
+
+/// Apply ticks of tree: existing lines stay on their places, newly ticked items
+/// are appended and unticked ones removed. Entries absent in SVD and comments are kept
+fn update_file(store: &TreeStore, svd_file: &SvdName, file: &mut RegistersFile) {
+    file.svd = svd_file.0.clone();
+    file.svd_inner = svd_file.1.clone();
+    if let Some(piter) = &store.iter_first() {
+        loop {
+            recursive_save(store, piter, file);
+            if !store.iter_next(piter) {
+                break;
+            }
+        }
+    }
+}
+
+/// Update and write registers file, see [`update_file`]
+fn save_data(
+    store: &TreeStore,
+    output: &Path,
+    svd_file: &SvdName,
+    file: &mut RegistersFile,
+    version: Version,
+    export_to: Option<(Format, &Device)>,
+) -> anyhow::Result<()> {
+    update_file(store, svd_file, file);
+    file.write(output, version)?;
+    if let Some((format, device)) = export_to {
+        let path = output.with_extension(format.extension());
+        let doc = export(file, Some(device), format)?;
+        fs::write(&path, doc).with_context(|| format!("Unable to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Write GDB script setting watchpoints on ticked items to file chosen by user.
+/// Registers file itself is not changed
+fn save_watchpoints(
+    window: &gtk::Window,
+    store: &TreeStore,
+    output: &Path,
+    svd_file: &SvdName,
+    mut file: RegistersFile,
+    kind: Watch,
+) -> anyhow::Result<()> {
+    update_file(store, svd_file, &mut file);
+    if file.entries().next().is_none() {
+        bail!("No registers or fields are ticked");
+    }
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some("Save watchpoints"),
+        Some(window),
+        gtk::FileChooserAction::Save,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Save", gtk::ResponseType::Ok),
+        ],
+    );
+    dialog.set_do_overwrite_confirmation(true);
+    if let Some(dir) = output.parent().filter(|d| !d.as_os_str().is_empty()) {
+        dialog.set_current_folder(dir);
+    }
+    let name = output.with_extension(format!("{}.gdb", kind.command()));
+    dialog.set_current_name(name.file_name().unwrap().to_str().unwrap_or("watch.gdb"));
+    let path = match dialog.run() {
+        gtk::ResponseType::Ok => dialog.filename(),
+        _ => None,
+    };
+    dialog.close();
+    if let Some(path) = path {
+        fs::write(&path, gdb::watchpoints(file.entries(), kind))
+            .with_context(|| format!("Unable to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Format of structured document written next to `registers.txt`
+fn selected_export(combo: &gtk::ComboBoxText) -> Option<Format> {
+    combo.active_id().and_then(|id| id.parse().ok())
+}
+
+/// Format of saved `registers.txt`. Files of version 1 are upgraded unless v1 is chosen
+fn selected_version(combo: &gtk::ComboBoxText) -> Version {
+    combo
+        .active_id()
+        .and_then(|id| id.parse().ok())
+        .unwrap_or_default()
+}
+
+fn on_toggle(window: &gtk::Window, st: &TreeStore, path: &TreePath) {
+    if let Some(iter) = st.iter(path) {
+        let current_value = !st.get_bool(&iter, 1);
+        let effects = st.get_string(&iter, 17);
+        let effects = effects.trim_start_matches('\u{26a0}').trim();
+        if current_value
+            && !effects.is_empty()
+            && !confirm_side_effects(window, &get_reg_path(st, &iter), effects)
+        {
+            return;
+        }
+        st.set_value(&iter, 1, &current_value.to_value());
+        println!(
+            "{} {}",
+            get_reg_path(st, &iter),
+            if current_value { "enabled" } else { "disabled" }
+        );
+    }
+}
+
+/// Ask whether register or field with side effects should be monitored
+fn confirm_side_effects(window: &gtk::Window, path: &str, effects: &str) -> bool {
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::YesNo,
+        &format!("{} has side effects ({})", path, effects),
+    );
+    dialog.set_secondary_text(Some(
+        "Reading it in dashboard on every step or setting its value can change device state.\n\
+         Monitor it anyway?",
+    ));
+    let response = dialog.run();
+    dialog.close();
+    response == gtk::ResponseType::Yes
+}
+
+trait GetValue {
+    fn get_bool(&self, iter: &TreeIter, ncol: i32) -> bool;
+    fn get_string(&self, iter: &TreeIter, ncol: i32) -> String;
+}
+
+impl GetValue for TreeStore {
+    fn get_bool(&self, iter: &TreeIter, ncol: i32) -> bool {
+        self.value(iter, ncol).get::<bool>().unwrap_or_default()
+    }
+    fn get_string(&self, iter: &TreeIter, ncol: i32) -> String {
+        self.value(iter, ncol).get::<String>().unwrap_or_default()
+    }
+}
@@ -0,0 +1,28 @@
+//! Resolved register tree of SVD device.
+//!
+//! Used by `svdselector` GUI and command line, but doesn't depend on GTK.
+
+pub use svd_parser::svd;
+
+pub mod archive;
+pub mod cache;
+pub mod decode;
+mod derive;
+pub mod dump;
+pub mod export;
+pub mod gdb;
+pub mod openocd;
+pub mod pack;
+pub mod pyocd;
+pub mod registers;
+pub mod search;
+mod tree;
+
+pub use tree::{Device, EnumValue, EnumValues, Kind, Lookup, Node, ReadAction};
+
+/// Replace repeated whitespaces (SVD descriptions are often multiline) with single space
+pub fn rm_white(text: &str) -> String {
+    use lazy_regex::regex;
+    let re = regex!(r"\s\s+");
+    re.replace_all(text, " ").to_string()
+}
@@ -0,0 +1,67 @@
+//! OpenOCD Tcl procedure printing monitored registers and fields.
+//!
+//! After `script registers.tcl` OpenOCD command `svd_registers` reads every item with
+//! `mdb`/`mdh`/`mdw`/`mdd` of register size and prints register or extracted field value.
+//! Items with `readAction` are not read, `<read clear>` is printed instead.
+
+use crate::archive;
+use crate::registers::{field_mask, Entry, RegistersFile};
+
+use std::path::Path;
+
+/// Script defining `svd_registers` procedure for all entries of `registers.txt`
+pub fn script(file: &RegistersFile) -> String {
+    let mut s = format!(
+        "# OpenOCD procedure generated by svdselector\n\
+         # SVD: {}\n\
+         # Use: script this file, then `svd_registers`\n\n\
+         # Value read by mdb/mdh/mdw/mdd, their output is \"ADDRESS: VALUE\"\n\
+         proc svd_read {{md address}} {{\n    return [expr 0x[lindex [$md $address] 1]]\n}}\n\n\
+         proc svd_registers {{}} {{\n",
+        archive::display_name(Path::new(&file.svd), file.svd_inner.as_deref())
+    );
+    for entry in file.entries() {
+        s += &read(entry);
+    }
+    s += "}\n";
+    s
+}
+
+fn read(entry: &Entry) -> String {
+    let size = entry.access_size();
+    let md = match size {
+        8 => "mdb",
+        16 => "mdh",
+        64 => "mdd",
+        _ => "mdw",
+    };
+    // Reading changes device state, like dashboard such items are not read.
+    // Braces keep brackets of array names from substitution
+    if let Some(ra) = entry.read_action {
+        return format!(
+            "    # {}\n    echo {{{} = <read {}>}}\n",
+            entry.path,
+            entry.path,
+            ra.as_str()
+        );
+    }
+    let mut s = format!(
+        "    # {}\n    set value [svd_read {} 0x{:08x}]\n",
+        entry.path, md, entry.address
+    );
+    let path = entry.path.replace('%', "%%");
+    s += &match entry.bits {
+        None => format!(
+            "    echo [format {{{} = 0x%0{}x}} $value]\n",
+            path,
+            size / 4
+        ),
+        Some((offset, width)) => format!(
+            "    echo [format {{{} = %d}} [expr {{($value >> {}) & 0x{:x}}}]]\n",
+            path,
+            offset,
+            field_mask(width)
+        ),
+    };
+    s
+}
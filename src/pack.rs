@@ -0,0 +1,245 @@
+//! Finding SVD of device in CMSIS-Packs.
+//!
+//! Pack description (`.pdsc`) lists devices in `<family>`, `<subFamily>`, `<device>` and
+//! `<variant>` elements. `<debug svd="...">` of any of them applies to all devices inside.
+//! Packs can be unpacked directories or `.pack` archives, e.g. pack cache of Keil or
+//! `~/.cache/arm/packs`.
+
+use crate::archive;
+use anyhow::{anyhow, bail, Context};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Packs are searched in subdirectories up to this depth (`Vendor/Pack/Version/*.pdsc`)
+const MAX_DEPTH: usize = 4;
+
+/// Part of path for sorting: numbers are compared as numbers
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Part {
+    Number(u64),
+    Text(String),
+}
+
+/// Sort key of path in which numbers of versions are compared numerically, so
+/// `Pack/2.9.0/` comes before `Pack/2.15.0/` and `DFP.2.9.0.pack` before `DFP.2.15.0.pack`.
+/// Pre-release `2.15.0-rc` comes before `2.15.0`, because `-` sorts before `.` and `/`
+fn version_order(path: &Path) -> Vec<Part> {
+    let path = path.to_string_lossy();
+    let mut parts = Vec::new();
+    let mut rest = &path[..];
+    while let Some(c) = rest.chars().next() {
+        let digit = c.is_ascii_digit();
+        let len = rest
+            .find(|c: char| c.is_ascii_digit() != digit)
+            .unwrap_or(rest.len());
+        let (part, tail) = rest.split_at(len);
+        parts.push(match part.parse() {
+            Ok(n) if digit => Part::Number(n),
+            _ => Part::Text(part.to_string()),
+        });
+        rest = tail;
+    }
+    parts
+}
+
+/// SVD of device described in pack
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceSvd {
+    /// Device or variant name
+    pub device: String,
+    /// SVD file, or `.pack` archive if `inner` is set
+    pub path: PathBuf,
+    /// Path of SVD inside `.pack` archive
+    pub inner: Option<String>,
+}
+
+/// All devices which have SVD in packs found in `dir`. `dir` can also be `.pdsc` or `.pack` file.
+/// Packs are in [`version_order`], so newer versions of same pack come later.
+/// Also returns errors of packs which can't be read, other packs are still used
+pub fn devices(dir: &Path) -> anyhow::Result<(Vec<DeviceSvd>, Vec<anyhow::Error>)> {
+    let mut files = Vec::new();
+    if dir.is_dir() {
+        find_packs(dir, 0, &mut files);
+    } else {
+        files.push(dir.to_path_buf());
+    }
+    files.sort_by_cached_key(|f| version_order(f));
+    let mut devices = Vec::new();
+    let mut errors = Vec::new();
+    for file in &files {
+        let result = if archive::is_archive(file) {
+            pack_devices(file)
+        } else {
+            pdsc_devices(file)
+        };
+        match result {
+            Ok(d) => devices.extend(d),
+            Err(e) => errors.push(e),
+        }
+    }
+    Ok((devices, errors))
+}
+
+/// Find SVD of device by name (case insensitive) among `devices` of packs in `dir`.
+/// If several packs describe device, last one is used, which is the newest version of
+/// pack in pack cache
+pub fn find_device(devices: &[DeviceSvd], dir: &Path, name: &str) -> anyhow::Result<DeviceSvd> {
+    let lower = name.to_lowercase();
+    if let Some(d) = devices
+        .iter()
+        .rev()
+        .find(|d| d.device.to_lowercase() == lower)
+    {
+        return Ok(d.clone());
+    }
+    let mut similar: Vec<&str> = devices
+        .iter()
+        .map(|d| d.device.as_str())
+        .filter(|d| d.to_lowercase().contains(&lower))
+        .collect();
+    similar.sort_unstable();
+    similar.dedup();
+    similar.truncate(20);
+    if similar.is_empty() {
+        bail!("device {} not found in {}", name, dir.display());
+    }
+    bail!(
+        "device {} not found in {}, similar devices:\n{}",
+        name,
+        dir.display(),
+        similar.join("\n")
+    )
+}
+
+fn find_packs(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth < MAX_DEPTH {
+                find_packs(&path, depth + 1, files);
+            }
+        } else if is_pdsc(&path) || archive::is_archive(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn is_pdsc(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("pdsc"))
+}
+
+/// Devices of unpacked pack. SVD paths are relative to `.pdsc` directory.
+/// Devices with missing SVD (e.g. index of pack cache) are skipped
+fn pdsc_devices(pdsc: &Path) -> anyhow::Result<Vec<DeviceSvd>> {
+    let xml =
+        fs::read_to_string(pdsc).with_context(|| format!("Unable to read {}", pdsc.display()))?;
+    let dir = pdsc.parent().unwrap_or_else(|| Path::new(""));
+    Ok(parse_pdsc(&xml)
+        .with_context(|| format!("Unable to parse {}", pdsc.display()))?
+        .into_iter()
+        .map(|(device, svd)| DeviceSvd {
+            device,
+            path: dir.join(svd),
+            inner: None,
+        })
+        .filter(|d| d.path.is_file())
+        .collect())
+}
+
+/// Devices of `.pack` archive, `.pdsc` is in its root
+fn pack_devices(pack: &Path) -> anyhow::Result<Vec<DeviceSvd>> {
+    let file =
+        fs::File::open(pack).with_context(|| format!("Unable to read {}", pack.display()))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .with_context(|| format!("{} is not zip archive", pack.display()))?;
+    let name = zip
+        .file_names()
+        .find(|n| !n.contains('/') && is_pdsc(Path::new(n)))
+        .map(String::from)
+        .ok_or_else(|| anyhow!("{} doesn't contain .pdsc", pack.display()))?;
+    let mut xml = String::new();
+    zip.by_name(&name)?.read_to_string(&mut xml)?;
+    Ok(parse_pdsc(&xml)
+        .with_context(|| format!("Unable to parse {}!{}", pack.display(), name))?
+        .into_iter()
+        .map(|(device, svd)| DeviceSvd {
+            device,
+            path: pack.to_path_buf(),
+            inner: Some(svd),
+        })
+        .collect())
+}
+
+/// Device and variant names with SVD paths (`/` separated). `<debug>` elements are
+/// inherited from family, subfamily and device
+fn parse_pdsc(xml: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let mut devices = Vec::new();
+    for devs in doc.descendants().filter(|n| n.has_tag_name("devices")) {
+        for family in devs.children().filter(|n| n.has_tag_name("family")) {
+            collect_devices(family, None, &mut devices);
+        }
+    }
+    Ok(devices)
+}
+
+fn collect_devices(node: roxmltree::Node, svd: Option<&str>, devices: &mut Vec<(String, String)>) {
+    let svd = node
+        .children()
+        .filter(|n| n.has_tag_name("debug"))
+        .find_map(|n| n.attribute("svd"))
+        .or(svd);
+    let name = match node.tag_name().name() {
+        "device" => node.attribute("Dname"),
+        "variant" => node.attribute("Dvariant"),
+        _ => None,
+    };
+    if let (Some(name), Some(svd)) = (name, svd) {
+        devices.push((name.to_string(), svd.replace('\\', "/")));
+    }
+    for child in node.children().filter(|n| {
+        n.has_tag_name("subFamily") || n.has_tag_name("device") || n.has_tag_name("variant")
+    }) {
+        collect_devices(child, svd, devices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newest_version_last() {
+        let mut paths: Vec<PathBuf> = [
+            "Keil/STM32F4xx_DFP/2.15.0/Keil.STM32F4xx_DFP.pdsc",
+            "Keil/STM32F4xx_DFP/2.9.0/Keil.STM32F4xx_DFP.pdsc",
+            "Keil/STM32F4xx_DFP/2.15.0-rc/Keil.STM32F4xx_DFP.pdsc",
+            "Keil/STM32F4xx_DFP/2.10.0/Keil.STM32F4xx_DFP.pdsc",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        paths.sort_by_cached_key(|p| version_order(p));
+        let versions: Vec<_> = paths
+            .iter()
+            .map(|p| p.iter().nth(2).unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(versions, ["2.9.0", "2.10.0", "2.15.0-rc", "2.15.0"]);
+
+        let mut packs = [
+            PathBuf::from("Keil.STM32F4xx_DFP.2.15.0.pack"),
+            PathBuf::from("Keil.STM32F4xx_DFP.2.9.0.pack"),
+        ];
+        packs.sort_by_cached_key(|p| version_order(p));
+        assert_eq!(packs[1], Path::new("Keil.STM32F4xx_DFP.2.15.0.pack"));
+    }
+}
@@ -0,0 +1,59 @@
+//! pyOCD user script printing monitored registers and fields.
+//!
+//! `pyocd commander --script registers.py` adds `svd_registers` command, which reads
+//! every item with `target.read8`..`read64` of register size and prints register or
+//! extracted field value. Items with `readAction` are not read, `<read clear>` is
+//! printed instead.
+
+use crate::archive;
+use crate::registers::RegistersFile;
+
+use std::path::Path;
+
+/// Script defining `svd_registers` command for all entries of `registers.txt`
+pub fn script(file: &RegistersFile) -> String {
+    let mut s = format!(
+        "# pyOCD user script generated by svdselector\n\
+         # SVD: {}\n\
+         # Use: pyocd commander --script this file, then `svd_registers`\n\n\
+         # Path, address, access size in bits, bit offset and width of field,\n\
+         # readAction if reading changes device state (such items are not read)\n\
+         REGISTERS = [\n",
+        archive::display_name(Path::new(&file.svd), file.svd_inner.as_deref())
+    );
+    for entry in file.entries() {
+        let (offset, width) = match entry.bits {
+            Some((offset, width)) => (offset.to_string(), width.to_string()),
+            None => ("None".to_string(), "None".to_string()),
+        };
+        s += &format!(
+            "    (\"{}\", 0x{:08x}, {}, {}, {}, {}),\n",
+            entry.path.replace('\\', "\\\\").replace('"', "\\\""),
+            entry.address,
+            entry.access_size(),
+            offset,
+            width,
+            match entry.read_action {
+                Some(ra) => format!("\"{}\"", ra.as_str()),
+                None => "None".to_string(),
+            }
+        );
+    }
+    s += r#"]
+
+
+@command(help="Print registers and fields selected in svdselector")
+def svd_registers():
+    read = {8: target.read8, 16: target.read16, 32: target.read32, 64: target.read64}
+    for path, address, size, offset, width, read_action in REGISTERS:
+        if read_action is not None:
+            print("%s = <read %s>" % (path, read_action))
+            continue
+        value = read[size](address)
+        if offset is None:
+            print("%s = 0x%0*x" % (path, size // 4, value))
+        else:
+            print("%s = %d" % (path, (value >> offset) & ((1 << width) - 1)))
+"#;
+    s
+}
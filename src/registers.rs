@@ -0,0 +1,592 @@
+//! `registers.txt` read by dashboard module.
+//!
+//! Lines are monitored registers (`PATH ALIAS ADDRESS`) and fields
+//! (`PATH ALIAS ADDRESS BIT_OFFSET BIT_WIDTH`). Lines starting with `#` are comments.
+//! Optional `KEY=VALUE` attributes follow values: `size=16` is register size in bits,
+//! written only if it is not 32, `read=clear` is `readAction` of register or field,
+//! so reading it changes device state.
+//!
+//! Version 1: first line is SVD file name, values are separated by whitespaces,
+//! `_` means empty alias.
+//!
+//! Version 2: first line is [`V2_HEADER`], second is SVD file name, followed by path
+//! of SVD inside archive if SVD file is zip or CMSIS-Pack. Values containing
+//! whitespaces or quotes are written in double quotes with `\"` and `\\` escapes
+//! (same rules as Python `shlex.split`), empty alias is `""`.
+
+use crate::{Node, ReadAction};
+
+use anyhow::{anyhow, bail, Context};
+use std::{borrow::Cow, fmt, fs, path::Path, str::FromStr};
+
+/// First line of version 2 file
+pub const V2_HEADER: &str = "# svdregisters v2";
+
+/// Format version of `registers.txt`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Version {
+    V1,
+    #[default]
+    V2,
+}
+
+impl FromStr for Version {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" | "v1" => Ok(Self::V1),
+            "2" | "v2" => Ok(Self::V2),
+            _ => Err(anyhow!("unknown registers file version `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        })
+    }
+}
+
+/// Monitored register or field
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// Path in SVD
+    pub path: String,
+    /// Name shown in dashboard. `None` if same as path
+    pub alias: Option<String>,
+    pub address: u64,
+    /// Bit offset and bit width of field
+    pub bits: Option<(u32, u32)>,
+    /// Register size in bits. `None` means 32
+    pub size: Option<u32>,
+    /// Reading has side effect, so it shouldn't be read automatically
+    pub read_action: Option<ReadAction>,
+}
+
+impl Entry {
+    /// Entry for register or field of SVD tree
+    pub fn from_node(node: &Node, alias: Option<String>) -> Self {
+        Self {
+            path: node.path.clone(),
+            alias,
+            address: node.address.unwrap_or_default(),
+            bits: node.bits,
+            size: node.properties.size,
+            read_action: node.read_side_effect(),
+        }
+    }
+
+    /// Width in bits of memory access reading register: 8, 16, 32 or 64
+    pub fn access_size(&self) -> u32 {
+        match self.size.unwrap_or(32).div_ceil(8) {
+            1 => 8,
+            2 => 16,
+            5..=8 => 64,
+            _ => 32,
+        }
+    }
+
+    /// Parse line of file with given format version
+    pub fn parse(line: &str, version: Version) -> Result<Self, String> {
+        let ws: Vec<Cow<str>> = match version {
+            Version::V1 => line.split_whitespace().map(Cow::from).collect(),
+            Version::V2 => split_quoted(line)?.into_iter().map(Cow::from).collect(),
+        };
+        // Attributes can't be first 3 values, so alias containing `=` is allowed
+        let nvalues = ws
+            .iter()
+            .skip(3)
+            .position(|w| w.contains('='))
+            .map_or(ws.len(), |n| n + 3);
+        let (ws, attrs) = ws.split_at(nvalues);
+        if ws.len() != 3 && ws.len() != 5 {
+            return Err(format!(
+                "expected `PATH ALIAS ADDRESS [BIT_OFFSET BIT_WIDTH]`, found {} values",
+                ws.len()
+            ));
+        }
+        let mut size = None;
+        let mut read_action = None;
+        for attr in attrs {
+            match attr.split_once('=') {
+                Some(("size", value)) => {
+                    size = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|s| (1..=64).contains(s))
+                            .ok_or_else(|| format!("invalid size `{}`", value))?,
+                    )
+                }
+                Some(("read", value)) => {
+                    read_action = Some(
+                        ReadAction::parse_str(value)
+                            .ok_or_else(|| format!("invalid read action `{}`", value))?,
+                    )
+                }
+                _ => return Err(format!("unknown attribute `{}`", attr)),
+            }
+        }
+        let address = parse_number(&ws[2]).ok_or_else(|| format!("invalid address `{}`", ws[2]))?;
+        let bits = if ws.len() == 5 {
+            let offset = ws[3]
+                .parse()
+                .map_err(|_| format!("invalid bit offset `{}`", ws[3]))?;
+            let width: u32 = ws[4]
+                .parse()
+                .map_err(|_| format!("invalid bit width `{}`", ws[4]))?;
+            if width == 0 || offset + width > 64 {
+                return Err(format!("invalid bit range {}+{}", offset, width));
+            }
+            Some((offset, width))
+        } else {
+            None
+        };
+        let no_alias = match version {
+            Version::V1 => "_",
+            Version::V2 => "",
+        };
+        Ok(Self {
+            path: ws[0].to_string(),
+            alias: Some(&ws[1])
+                .filter(|a| *a != no_alias)
+                .map(|a| a.to_string()),
+            address,
+            bits,
+            size,
+            read_action,
+        })
+    }
+
+    /// Line of file with given format version
+    pub fn to_line(&self, version: Version) -> Result<String, String> {
+        let mut line = match version {
+            Version::V1 => {
+                let alias = self.alias.as_deref().unwrap_or("_");
+                for s in [&self.path, alias].iter() {
+                    if s.is_empty() || s.contains(char::is_whitespace) {
+                        return Err(format!("`{}` can't be written in v1 format", s));
+                    }
+                }
+                format!("{} {} 0x{:08x}", self.path, alias, self.address)
+            }
+            Version::V2 => format!(
+                "{} {} 0x{:08x}",
+                quote(&self.path),
+                quote(self.alias.as_deref().unwrap_or_default()),
+                self.address
+            ),
+        };
+        if let Some((offset, width)) = self.bits {
+            line += &format!(" {} {}", offset, width);
+        }
+        // Dashboard reading version 1 doesn't know attributes
+        if version == Version::V1 {
+            return Ok(line);
+        }
+        match self.size {
+            Some(32) | None => {}
+            Some(size) => line += &format!(" size={}", size),
+        }
+        if let Some(ra) = self.read_action {
+            line += &format!(" read={}", ra.as_str());
+        }
+        Ok(line)
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_line(Version::V2).unwrap_or_default())
+    }
+}
+
+/// Quote value if it is empty or contains whitespaces, quotes, backslashes or `#`
+pub fn quote(s: &str) -> Cow<'_, str> {
+    if !s.is_empty() && !s.contains(|c: char| c.is_whitespace() || "\"'\\#".contains(c)) {
+        return Cow::from(s);
+    }
+    let mut q = String::from("\"");
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            q.push('\\');
+        }
+        q.push(c);
+    }
+    q.push('"');
+    Cow::from(q)
+}
+
+/// Split line in values like Python `shlex.split`: whitespaces separate values,
+/// `"` and `'` quote them, `\` escapes next character
+pub fn split_quoted(line: &str) -> Result<Vec<String>, String> {
+    let mut values = Vec::new();
+    let mut chars = line.chars();
+    let mut value: Option<String> = None;
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => values.extend(value.take()),
+            '\\' => {
+                let c = chars.next().ok_or("no character after `\\`")?;
+                value.get_or_insert_with(String::new).push(c);
+            }
+            '"' => {
+                let v = value.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or("missing closing `\"`")? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or("missing closing `\"`")? {
+                            c @ '"' | c @ '\\' => v.push(c),
+                            c => {
+                                v.push('\\');
+                                v.push(c);
+                            }
+                        },
+                        c => v.push(c),
+                    }
+                }
+            }
+            '\'' => {
+                let v = value.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or("missing closing `'`")? {
+                        '\'' => break,
+                        c => v.push(c),
+                    }
+                }
+            }
+            c => value.get_or_insert_with(String::new).push(c),
+        }
+    }
+    values.extend(value);
+    Ok(values)
+}
+
+/// Parse hexadecimal (`0x` prefixed) or decimal number
+pub fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => s.replace('_', "").parse().ok(),
+    }
+}
+
+/// Mask of `width` low bits
+pub fn field_mask(width: u32) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// Line of `registers.txt` after SVD file name (and header)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line {
+    Entry(Entry),
+    /// Comment or empty line
+    Comment(String),
+    /// Line which can't be parsed, kept as is
+    Invalid(String),
+}
+
+/// Description of line which can't be parsed
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineError {
+    /// Line number starting from 1
+    pub line: usize,
+    pub text: String,
+    pub message: String,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}: `{}`", self.line, self.message, self.text)
+    }
+}
+
+/// Parsed content of `registers.txt`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegistersFile {
+    /// Format version file was read with
+    pub version: Version,
+    /// SVD file name
+    pub svd: String,
+    /// Path of SVD inside archive `svd`. Only version 2 can store it
+    pub svd_inner: Option<String>,
+    pub lines: Vec<Line>,
+}
+
+impl RegistersFile {
+    pub fn new(svd: &str) -> Self {
+        Self {
+            version: Version::default(),
+            svd: svd.to_string(),
+            svd_inner: None,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Parse file of any version. Lines which can't be parsed are kept as [`Line::Invalid`]
+    pub fn parse(text: &str) -> anyhow::Result<(Self, Vec<LineError>)> {
+        let mut lines = text.lines().map(str::trim).enumerate();
+        let first = lines.next().map(|(_, l)| l).unwrap_or_default();
+        let mut file = if first == V2_HEADER {
+            let values = match lines.next() {
+                Some((_, l)) => split_quoted(l).map_err(|e| anyhow!("line 2: {}", e))?,
+                None => Vec::new(),
+            };
+            let mut values = values.into_iter();
+            let mut file = Self::new(&values.next().unwrap_or_default());
+            file.svd_inner = values.next();
+            file
+        } else if first.starts_with("# svdregisters v") {
+            bail!("unsupported format `{}`", first);
+        } else {
+            let mut file = Self::new(first);
+            file.version = Version::V1;
+            file
+        };
+        let mut errors = Vec::new();
+        for (i, l) in lines {
+            file.lines.push(if l.is_empty() || l.starts_with('#') {
+                Line::Comment(l.to_string())
+            } else {
+                match Entry::parse(l, file.version) {
+                    Ok(entry) => Line::Entry(entry),
+                    Err(message) => {
+                        errors.push(LineError {
+                            line: i + 1,
+                            text: l.to_string(),
+                            message,
+                        });
+                        Line::Invalid(l.to_string())
+                    }
+                }
+            });
+        }
+        Ok((file, errors))
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<(Self, Vec<LineError>)> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Unable to parse {}", path.display()))
+    }
+
+    /// File content in given format version
+    pub fn serialize(&self, version: Version) -> anyhow::Result<String> {
+        let mut s = match (version, &self.svd_inner) {
+            (Version::V1, None) => format!("{}\n", self.svd),
+            (Version::V1, Some(_)) => bail!("SVD inside archive can't be written in version 1"),
+            (Version::V2, None) => format!("{}\n{}\n", V2_HEADER, quote(&self.svd)),
+            (Version::V2, Some(inner)) => {
+                format!("{}\n{} {}\n", V2_HEADER, quote(&self.svd), quote(inner))
+            }
+        };
+        for l in &self.lines {
+            match l {
+                Line::Entry(e) => s += &e.to_line(version).map_err(|e| anyhow!(e))?,
+                Line::Comment(c) | Line::Invalid(c) => s += c,
+            }
+            s.push('\n');
+        }
+        Ok(s)
+    }
+
+    pub fn write(&self, path: &Path, version: Version) -> anyhow::Result<()> {
+        let s = self.serialize(version)?;
+        fs::write(path, s).with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|l| match l {
+            Line::Entry(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    pub fn find_mut(&mut self, path: &str) -> Option<&mut Entry> {
+        self.lines.iter_mut().find_map(|l| match l {
+            Line::Entry(e) if e.path == path => Some(e),
+            _ => None,
+        })
+    }
+
+    /// Replace entry with same path or add new one at the end
+    pub fn insert(&mut self, entry: Entry) {
+        match self.find_mut(&entry.path) {
+            Some(e) => *e = entry,
+            None => self.lines.push(Line::Entry(entry)),
+        }
+    }
+
+    /// Remove entry. Returns `false` if it is absent
+    pub fn remove(&mut self, path: &str) -> bool {
+        let len = self.lines.len();
+        self.lines
+            .retain(|l| !matches!(l, Line::Entry(e) if e.path == path));
+        self.lines.len() != len
+    }
+
+    pub fn remove_invalid(&mut self) {
+        self.lines.retain(|l| !matches!(l, Line::Invalid(_)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(alias: Option<&str>) -> Entry {
+        Entry {
+            path: "USART1.SR".to_string(),
+            alias: alias.map(String::from),
+            address: 0x4001_1000,
+            bits: None,
+            size: None,
+            read_action: None,
+        }
+    }
+
+    #[test]
+    fn split_like_shlex() {
+        // Expected values are results of Python `shlex.split`
+        assert_eq!(
+            split_quoted(r#"P "a \"b\" \\c" 'd e' f#g x\ y"#).unwrap(),
+            ["P", "a \"b\" \\c", "d e", "f#g", "x y"]
+        );
+        assert_eq!(
+            split_quoted(r#"P "a\$b\`c\d" "#).unwrap(),
+            ["P", r"a\$b\`c\d"]
+        );
+        assert_eq!(
+            split_quoted(r#"P "" 0x1 size=16"#).unwrap(),
+            ["P", "", "0x1", "size=16"]
+        );
+        assert_eq!(
+            split_quoted(r#"P "it's" 0x1"#).unwrap(),
+            ["P", "it's", "0x1"]
+        );
+        assert!(split_quoted(r#"P "open"#).is_err());
+        assert!(split_quoted(r"P end\").is_err());
+    }
+
+    #[test]
+    fn quoted_alias_round_trip() {
+        for alias in &[
+            "with space",
+            "quo\"te",
+            "back\\slash",
+            "hash#tag",
+            "it's",
+            "tab\there",
+            "a=b",
+            "_",
+        ] {
+            let e = entry(Some(alias));
+            let line = e.to_line(Version::V2).unwrap();
+            assert_eq!(Entry::parse(&line, Version::V2), Ok(e), "{}", line);
+        }
+        assert_eq!(quote("with space"), r#""with space""#);
+        assert_eq!(quote("quo\"te"), r#""quo\"te""#);
+        assert_eq!(quote("back\\slash"), r#""back\\slash""#);
+        assert_eq!(quote("hash#tag"), r##""hash#tag""##);
+        assert_eq!(quote("a=b"), "a=b");
+        assert_eq!(quote(""), r#""""#);
+    }
+
+    #[test]
+    fn alias_with_equal_sign() {
+        let e = Entry::parse("USART1.SR a=b 0x40011000 read=clear", Version::V2).unwrap();
+        assert_eq!(e.alias.as_deref(), Some("a=b"));
+        assert_eq!(e.read_action, Some(ReadAction::Clear));
+    }
+
+    #[test]
+    fn empty_alias() {
+        let v1 = Entry::parse("USART1.SR _ 0x40011000", Version::V1).unwrap();
+        assert_eq!(v1, entry(None));
+        assert_eq!(v1.to_line(Version::V1).unwrap(), "USART1.SR _ 0x40011000");
+        assert_eq!(
+            v1.to_line(Version::V2).unwrap(),
+            r#"USART1.SR "" 0x40011000"#
+        );
+        // `_` is ordinary alias in version 2
+        let v2 = Entry::parse("USART1.SR _ 0x40011000", Version::V2).unwrap();
+        assert_eq!(v2, entry(Some("_")));
+        assert!(entry(Some("with space")).to_line(Version::V1).is_err());
+    }
+
+    #[test]
+    fn attributes() {
+        let line = r#"USART1.DR "" 0x40011004 size=16 read=clear"#;
+        let e = Entry::parse(line, Version::V2).unwrap();
+        assert_eq!(e.size, Some(16));
+        assert_eq!(e.read_action, Some(ReadAction::Clear));
+        assert_eq!(e.to_line(Version::V2).unwrap(), line);
+        assert_eq!(e.to_line(Version::V1).unwrap(), "USART1.DR _ 0x40011004");
+
+        let field = Entry::parse("USART1.CR1.UE ue 0x4001100c 13 1 size=32", Version::V2).unwrap();
+        assert_eq!(field.bits, Some((13, 1)));
+        // Default size is not written
+        assert_eq!(
+            field.to_line(Version::V2).unwrap(),
+            "USART1.CR1.UE ue 0x4001100c 13 1"
+        );
+
+        for invalid in &[
+            r#"USART1.DR "" 0x40011004 size=abc"#,
+            r#"USART1.DR "" 0x40011004 size=0"#,
+            r#"USART1.DR "" 0x40011004 read=never"#,
+            r#"USART1.DR "" 0x40011004 color=red"#,
+            r#"USART1.DR "" 0x40011004 size=16 junk"#,
+        ] {
+            assert!(Entry::parse(invalid, Version::V2).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn upgrade_v1_to_v2() {
+        let v1 = "test.svd\n\
+                  # comment\n\
+                  USART1.SR _ 0x40011000\n\
+                  USART1.CR1.UE ue 0x4001100c 13 1\n\
+                  not an entry\n";
+        let (file, errors) = RegistersFile::parse(v1).unwrap();
+        assert_eq!(file.version, Version::V1);
+        assert_eq!(file.svd, "test.svd");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 5);
+        let v2 = file.serialize(Version::V2).unwrap();
+        assert_eq!(
+            v2,
+            "# svdregisters v2\n\
+             test.svd\n\
+             # comment\n\
+             USART1.SR \"\" 0x40011000\n\
+             USART1.CR1.UE ue 0x4001100c 13 1\n\
+             not an entry\n"
+        );
+        let (upgraded, _) = RegistersFile::parse(&v2).unwrap();
+        assert_eq!(upgraded.version, Version::V2);
+        assert!(upgraded.entries().eq(file.entries()));
+        // And back
+        assert_eq!(upgraded.serialize(Version::V1).unwrap(), v1);
+    }
+
+    #[test]
+    fn svd_inside_archive() {
+        let mut file = RegistersFile::new("Keil DFP.pack");
+        file.svd_inner = Some("CMSIS/SVD/STM32F401.svd".to_string());
+        let text = file.serialize(Version::V2).unwrap();
+        assert_eq!(
+            text,
+            "# svdregisters v2\n\"Keil DFP.pack\" CMSIS/SVD/STM32F401.svd\n"
+        );
+        assert_eq!(RegistersFile::parse(&text).unwrap().0, file);
+        assert!(file.serialize(Version::V1).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! Matching of register tree rows by path, description or address
+
+use regex::{Regex, RegexBuilder};
+use std::str::FromStr;
+
+/// How search text is interpreted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Case-insensitive substring
+    Substring,
+    /// Case-insensitive whole string glob with `*` and `?`
+    Glob,
+    /// Case-insensitive regular expression
+    Regex,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "substring" => Ok(Self::Substring),
+            "glob" => Ok(Self::Glob),
+            "regex" => Ok(Self::Regex),
+            _ => Err(anyhow::anyhow!("unknown search mode `{}`", s)),
+        }
+    }
+}
+
+/// Compiled search text
+#[derive(Clone, Debug)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(text: &str, mode: Mode) -> anyhow::Result<Self> {
+        let re = match mode {
+            Mode::Substring => regex::escape(text),
+            Mode::Glob => {
+                let re = regex::escape(text).replace(r"\*", ".*").replace(r"\?", ".");
+                format!("^{}$", re)
+            }
+            Mode::Regex => text.to_string(),
+        };
+        Ok(Self(RegexBuilder::new(&re).case_insensitive(true).build()?))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    /// Path, description or address of tree row matches
+    pub fn matches(&self, path: &str, description: &str, address: &str) -> bool {
+        self.is_match(path)
+            || self.is_match(description)
+            || (!address.is_empty() && self.is_match(address))
+    }
+}
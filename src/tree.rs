@@ -0,0 +1,1343 @@
+use crate::archive;
+use crate::derive::expand_derived;
+use crate::rm_white;
+use crate::svd::{
+    self, Access, Cluster, DeriveFrom, Field, ModifiedWriteValues, Register, RegisterCluster,
+    RegisterInfo, RegisterProperties,
+};
+use anyhow::{anyhow, bail, Context};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::ops::Range;
+use std::path::Path;
+
+/// Kind of tree node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Kind {
+    Peripheral,
+    Cluster,
+    ClusterArray,
+    Register,
+    RegisterArray,
+    Field,
+    FieldArray,
+}
+
+impl Kind {
+    /// Short name used in GUI model
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Peripheral => "p",
+            Self::Cluster => "c",
+            Self::ClusterArray => "ca",
+            Self::Register => "r",
+            Self::RegisterArray => "ra",
+            Self::Field => "f",
+            Self::FieldArray => "fa",
+        }
+    }
+
+    /// Only single registers and fields (also array elements) can be monitored
+    pub fn is_selectable(self) -> bool {
+        matches!(self, Self::Register | Self::Field)
+    }
+}
+
+/// Side effect of reading register or field (`readAction`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReadAction {
+    Clear,
+    Set,
+    Modify,
+    ModifyExternal,
+}
+
+impl ReadAction {
+    /// Parse value as written in SVD
+    pub fn parse_str(s: &str) -> Option<Self> {
+        match s {
+            "clear" => Some(Self::Clear),
+            "set" => Some(Self::Set),
+            "modify" => Some(Self::Modify),
+            "modifyExternal" => Some(Self::ModifyExternal),
+            _ => None,
+        }
+    }
+
+    /// Value as written in SVD
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Clear => "clear",
+            Self::Set => "set",
+            Self::Modify => "modify",
+            Self::ModifyExternal => "modifyExternal",
+        }
+    }
+}
+
+/// Value of enumerated field
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnumValue {
+    pub name: String,
+    pub description: String,
+    pub value: Option<u64>,
+}
+
+/// Set of enumerated values of field
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnumValues {
+    pub name: Option<String>,
+    /// Path to original `enumeratedValues` as written in SVD
+    pub derived_from: Option<String>,
+    pub values: Vec<EnumValue>,
+}
+
+/// Peripheral, cluster, register or field with resolved address
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub kind: Kind,
+    /// Name with substituted array index
+    pub name: String,
+    /// Full dot-separated path starting from peripheral name
+    pub path: String,
+    /// Absolute address. `None` for arrays
+    pub address: Option<u64>,
+    /// Offset from parent address
+    pub offset: u32,
+    pub description: String,
+    /// Bit offset and bit width of field
+    pub bits: Option<(u32, u32)>,
+    /// Size, access, reset value and reset mask with defaults inherited from
+    /// cluster, peripheral and device. Reset value and mask of field are shifted to bit 0
+    pub properties: RegisterProperties,
+    /// `readAction` of register or field. Fields inherit it from register
+    pub read_action: Option<ReadAction>,
+    /// `modifiedWriteValues` of field
+    pub modified_write_values: Option<ModifiedWriteValues>,
+    /// Name of peripheral, cluster, register or field this one is derived from
+    pub derived_from: Option<String>,
+    pub enum_values: Vec<EnumValues>,
+    /// Absolute address ranges of `addressBlock`s of peripheral
+    pub address_blocks: Vec<Range<u64>>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    fn new(kind: Kind, name: &str, path: String) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            path,
+            address: None,
+            offset: 0,
+            description: String::new(),
+            bits: None,
+            properties: RegisterProperties::default(),
+            read_action: None,
+            modified_write_values: None,
+            derived_from: None,
+            enum_values: Vec::new(),
+            address_blocks: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Reading changes device state: `readAction` of node or of its fields
+    pub fn read_side_effect(&self) -> Option<ReadAction> {
+        self.read_action
+            .or_else(|| self.children.iter().find_map(|f| f.read_side_effect()))
+    }
+
+    /// Writing not only stores value (e.g. `oneToClear`): `modifiedWriteValues`
+    /// of field or of fields of register
+    pub fn write_side_effect(&self) -> Option<ModifiedWriteValues> {
+        self.modified_write_values
+            .filter(|m| *m != ModifiedWriteValues::Modify)
+            .or_else(|| self.children.iter().find_map(|f| f.write_side_effect()))
+    }
+
+    /// Memory occupied by register: address and size in bytes. `None` for other kinds
+    pub fn address_range(&self) -> Option<Range<u64>> {
+        match (self.kind, self.address) {
+            (Kind::Register, Some(address)) => {
+                let bytes = (self.properties.size.unwrap_or(32) as u64).div_ceil(8);
+                Some(address..address + bytes.max(1))
+            }
+            _ => None,
+        }
+    }
+
+    /// Depth-first iterator over node and all its descendants
+    pub fn iter(&self) -> impl Iterator<Item = &Node> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+}
+
+/// Result of [`Device::lookup`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lookup<'a> {
+    /// Register containing address and its fields which have bits in addressed byte
+    Register {
+        register: &'a Node,
+        fields: Vec<&'a Node>,
+    },
+    /// No register contains address, but it is in address block of peripheral
+    Block {
+        peripheral: &'a Node,
+        block: Range<u64>,
+    },
+}
+
+/// Register tree of whole device
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Device {
+    pub name: String,
+    pub peripherals: Vec<Node>,
+}
+
+impl Device {
+    /// Read and parse SVD file, which can be compressed or be file `inner` inside archive
+    pub fn load(svd_path: &Path, inner: Option<&str>) -> anyhow::Result<Self> {
+        let xml = archive::read_svd(svd_path, inner)?;
+        Self::parse(&xml)
+            .with_context(|| format!("Unable to parse {}", archive::display_name(svd_path, inner)))
+    }
+
+    /// Parse SVD from string
+    pub fn parse(xml: &str) -> anyhow::Result<Self> {
+        let device = svd_parser::parse(xml)?;
+        let read_actions = read_actions(xml)?;
+        Self::build(&device, &read_actions)
+    }
+
+    /// Build tree from parsed SVD device.
+    ///
+    /// `readAction` isn't parsed by `svd-rs`, so it is known only when tree is built with [`Device::parse`]
+    pub fn from_svd(device: &svd::Device) -> anyhow::Result<Self> {
+        Self::build(device, &ReadActions::new())
+    }
+
+    fn build(device: &svd::Device, ra: &ReadActions) -> anyhow::Result<Self> {
+        let (device, ra) = &expand_derived(device, ra)?;
+        let permap =
+            IndexMap::<&str, _>::from_iter(device.peripherals.iter().map(|i| (i.name.as_str(), i)));
+
+        let ev_map = enum_values_map(&permap);
+
+        let mut peripherals = Vec::with_capacity(permap.len());
+        for (pname, p) in &permap {
+            let mut pnode = Node::new(Kind::Peripheral, pname, pname.to_string());
+            pnode.address = Some(p.base_address);
+            pnode.description = rm_white(&p.description.to_owned().unwrap_or_default());
+            pnode.derived_from = p.derived_from.clone();
+            pnode.address_blocks = p
+                .address_block
+                .iter()
+                .flatten()
+                .map(|b| {
+                    let start = p.base_address + b.offset as u64;
+                    start..start + b.size as u64
+                })
+                .collect();
+            pnode.properties = p
+                .default_register_properties
+                .derive_from(&device.default_register_properties);
+            if let Some(rcs) = &p.registers {
+                let path = &pname.to_string();
+                let props = &pnode.properties;
+                for rc in rcs {
+                    let child = match rc {
+                        RegisterCluster::Register(reg) => {
+                            add_register_tree(&ev_map, ra, reg, path, path, p.base_address, props)?
+                        }
+                        RegisterCluster::Cluster(cl) => {
+                            add_cluster_tree(&ev_map, ra, cl, path, path, p.base_address, props)?
+                        }
+                    };
+                    pnode.children.push(child);
+                }
+            }
+            peripherals.push(pnode);
+        }
+        Ok(Self {
+            name: device.name.clone(),
+            peripherals,
+        })
+    }
+
+    /// Depth-first iterator over all nodes
+    pub fn iter(&self) -> impl Iterator<Item = &Node> {
+        self.peripherals.iter().flat_map(|p| p.iter())
+    }
+
+    /// Find node by full path
+    pub fn find(&self, path: &str) -> Option<&Node> {
+        let pname = path.split('.').next()?;
+        self.peripherals
+            .iter()
+            .find(|p| p.name == pname)?
+            .iter()
+            .find(|n| n.path == path)
+    }
+
+    /// Find register which contains `address`, or peripheral address block if there is no such register
+    pub fn lookup(&self, address: u64) -> Option<Lookup<'_>> {
+        let register = self.iter().find(|n| {
+            n.address_range()
+                .is_some_and(|range| range.contains(&address))
+        });
+        if let Some(register) = register {
+            let start = (address - register.address.unwrap_or_default()) as u32 * 8;
+            let fields = register
+                .iter()
+                .filter(|f| match (f.kind, f.bits) {
+                    (Kind::Field, Some((offset, width))) => {
+                        offset < start + 8 && offset + width > start
+                    }
+                    _ => false,
+                })
+                .collect();
+            return Some(Lookup::Register { register, fields });
+        }
+        self.peripherals.iter().find_map(|peripheral| {
+            peripheral
+                .address_blocks
+                .iter()
+                .find(|block| block.contains(&address))
+                .map(|block| Lookup::Block {
+                    peripheral,
+                    block: block.clone(),
+                })
+        })
+    }
+}
+
+/// Named `enumeratedValues` of all fields, keyed by full path
+/// `PERIPHERAL[.CLUSTER...].REGISTER.FIELD.NAME` (with unexpanded array names)
+#[derive(Default)]
+struct EvMap<'a> {
+    by_path: HashMap<String, &'a svd::EnumeratedValues>,
+    /// Full paths with same `enumeratedValues` name in document order
+    by_name: HashMap<&'a str, Vec<String>>,
+}
+
+impl<'a> EvMap<'a> {
+    fn insert(&mut self, path: String, evalues: &'a svd::EnumeratedValues, ev_name: &'a str) {
+        self.by_name.entry(ev_name).or_default().push(path.clone());
+        self.by_path.insert(path, evalues);
+    }
+
+    /// Find `enumeratedValues` referenced in `derivedFrom` of field with path `fpath`.
+    ///
+    /// Following CMSIS-SVD rules, reference is searched in field, register, cluster
+    /// and peripheral scopes, from inner to outer, and at last as absolute path.
+    /// Both qualified (`REGISTER.FIELD.NAME`) and unique in scope (`NAME`) references are allowed.
+    fn find(&self, fpath: &str, dname: &str) -> Option<(&str, &'a svd::EnumeratedValues)> {
+        let scopes = || {
+            std::iter::successors(Some(fpath), |s| s.rfind('.').map(|i| &s[..i]))
+                .map(|s| format!("{}.", s))
+                .chain(std::iter::once(String::new()))
+        };
+        for scope in scopes() {
+            if let Some((path, evalues)) = self.by_path.get_key_value(&(scope + dname)) {
+                return Some((path, evalues));
+            }
+        }
+        let ev_name = dname.rsplit('.').next()?;
+        let paths = self.by_name.get(ev_name)?;
+        let suffix = format!(".{}", dname);
+        for scope in scopes() {
+            if let Some(path) = paths
+                .iter()
+                .find(|p| p.starts_with(&scope) && p.ends_with(&suffix))
+            {
+                return Some((path, self.by_path[path]));
+            }
+        }
+        None
+    }
+
+    /// Resolve chain of `derivedFrom` for `enumeratedValues` of field with path `fpath`
+    fn resolve(
+        &self,
+        evalues: &'a svd::EnumeratedValues,
+        fpath: &str,
+        fname: &str,
+    ) -> anyhow::Result<&'a svd::EnumeratedValues> {
+        let mut evalues = evalues;
+        let mut fpath = fpath.to_string();
+        let mut depth = 0;
+        while let Some(dname) = &evalues.derived_from {
+            let (path, base) = self
+                .find(&fpath, dname)
+                .ok_or_else(|| anyhow!("Field {}: enumeratedValues {} not found", fname, dname))?;
+            depth += 1;
+            if depth > 16 {
+                bail!(
+                    "Field {}: derivedFrom loop in enumeratedValues {}",
+                    fname,
+                    dname
+                );
+            }
+            fpath = path[..path.rfind('.').unwrap_or(0)].to_string();
+            evalues = base;
+        }
+        Ok(evalues)
+    }
+}
+
+/// `readAction` of registers and fields keyed by path
+/// `PERIPHERAL[.CLUSTER...].REGISTER[.FIELD]` (with unexpanded array names)
+pub(crate) type ReadActions = HashMap<String, ReadAction>;
+
+/// `svd-rs` doesn't parse `readAction`, so it is read from XML.
+/// Read actions of derived elements are resolved by [`expand_derived`]
+fn read_actions(xml: &str) -> anyhow::Result<ReadActions> {
+    fn child_text<'a>(node: roxmltree::Node<'a, '_>, tag: &str) -> Option<&'a str> {
+        node.children()
+            .find(|c| c.has_tag_name(tag))
+            .and_then(|c| c.text())
+            .map(str::trim)
+    }
+
+    fn scan(node: roxmltree::Node, path: &str, map: &mut ReadActions) {
+        for child in node.children().filter(|c| c.is_element()) {
+            match child.tag_name().name() {
+                "cluster" | "register" | "field" => {
+                    let path =
+                        format!("{}.{}", path, child_text(child, "name").unwrap_or_default());
+                    if let Some(ra) =
+                        child_text(child, "readAction").and_then(ReadAction::parse_str)
+                    {
+                        map.insert(path.clone(), ra);
+                    }
+                    scan(child, &path, map);
+                }
+                "fields" => scan(child, path, map),
+                _ => {}
+            }
+        }
+    }
+
+    let doc = roxmltree::Document::parse(xml)?;
+    let mut map = ReadActions::new();
+    let peripherals = doc
+        .root_element()
+        .children()
+        .filter(|c| c.has_tag_name("peripherals"))
+        .flat_map(|ps| ps.children().filter(|p| p.has_tag_name("peripheral")));
+    for p in peripherals {
+        let pname = child_text(p, "name").unwrap_or_default();
+        if let Some(rs) = p.children().find(|c| c.has_tag_name("registers")) {
+            scan(rs, pname, &mut map);
+        }
+    }
+
+    Ok(map)
+}
+
+fn enum_values_map<'a>(permap: &IndexMap<&str, &'a svd::Peripheral>) -> EvMap<'a> {
+    fn add_reg_ev<'a>(ev_map: &mut EvMap<'a>, reg: &'a Register, regpath: &str) {
+        if let Some(fields) = &reg.fields {
+            for f in fields {
+                for evalues in &f.enumerated_values {
+                    if let Some(ev_name) = &evalues.name {
+                        ev_map.insert(
+                            format!("{}.{}.{}.{}", regpath, reg.name, f.name, ev_name),
+                            evalues,
+                            ev_name,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_clus_ev<'a>(ev_map: &mut EvMap<'a>, c: &'a Cluster, cpath: &str) {
+        for rc in &c.children {
+            match rc {
+                RegisterCluster::Register(reg) => {
+                    add_reg_ev(ev_map, reg, &format!("{}.{}", cpath, c.name))
+                }
+                RegisterCluster::Cluster(cl) => {
+                    add_clus_ev(ev_map, cl, &format!("{}.{}", cpath, c.name))
+                }
+            }
+        }
+    }
+
+    let mut ev_map = EvMap::default();
+    for (pname, p) in permap {
+        if let Some(rs) = &p.registers {
+            for rc in rs {
+                match rc {
+                    RegisterCluster::Register(reg) => add_reg_ev(&mut ev_map, reg, pname),
+                    RegisterCluster::Cluster(cl) => add_clus_ev(&mut ev_map, cl, pname),
+                }
+            }
+        }
+    }
+    ev_map
+}
+
+fn add_cluster_tree(
+    ev_map: &EvMap,
+    ra: &ReadActions,
+    c: &Cluster,
+    cpath: &str,
+    derpath: &str,
+    baseaddr: u64,
+    props: &RegisterProperties,
+) -> anyhow::Result<Node> {
+    let caddr = baseaddr + c.address_offset as u64;
+    let props = c.default_register_properties.derive_from(props);
+    let desc = rm_white(c.description.as_deref().unwrap_or(""));
+    let path = format!("{}.{}", cpath, c.name);
+    let derpath = format!("{}.{}", derpath, c.name);
+    match c {
+        Cluster::Single(c) => {
+            let mut cnode = Node::new(Kind::Cluster, &c.name, path);
+            cnode.address = Some(caddr);
+            cnode.offset = c.address_offset;
+            cnode.description = desc;
+            cnode.derived_from = c.derived_from.clone();
+            cnode.properties = props;
+            add_children(&mut cnode, ev_map, ra, &c.children, &derpath, caddr)?;
+            Ok(cnode)
+        }
+        Cluster::Array(c, dim) => {
+            let mut anode = Node::new(Kind::ClusterArray, &c.name, path);
+            anode.offset = c.address_offset;
+            anode.description = desc.clone();
+            anode.derived_from = c.derived_from.clone();
+            anode.properties = props;
+            for (i, idx) in dim.indexes().enumerate() {
+                let offset = dim.dim_increment * (i as u32);
+                let cname = c.name.replace("[%s]", &idx).replace("%s", &idx);
+                let mut cnode = Node::new(Kind::Cluster, &cname, format!("{}.{}", cpath, cname));
+                cnode.address = Some(caddr + offset as u64);
+                cnode.offset = c.address_offset + offset;
+                cnode.description = desc.replace("%s", &idx);
+                cnode.derived_from = c.derived_from.clone();
+                cnode.properties = props;
+                add_children(
+                    &mut cnode,
+                    ev_map,
+                    ra,
+                    &c.children,
+                    &derpath,
+                    caddr + offset as u64,
+                )?;
+                anode.children.push(cnode);
+            }
+            Ok(anode)
+        }
+    }
+}
+
+fn add_children(
+    node: &mut Node,
+    ev_map: &EvMap,
+    ra: &ReadActions,
+    rcs: &[RegisterCluster],
+    derpath: &str,
+    baseaddr: u64,
+) -> anyhow::Result<()> {
+    for rc in rcs {
+        let props = &node.properties;
+        let child = match rc {
+            RegisterCluster::Register(reg) => {
+                add_register_tree(ev_map, ra, reg, &node.path, derpath, baseaddr, props)?
+            }
+            RegisterCluster::Cluster(cl) => {
+                add_cluster_tree(ev_map, ra, cl, &node.path, derpath, baseaddr, props)?
+            }
+        };
+        node.children.push(child);
+    }
+    Ok(())
+}
+
+fn add_register_tree(
+    ev_map: &EvMap,
+    ra: &ReadActions,
+    r: &Register,
+    rpath: &str,
+    derpath: &str,
+    baseaddr: u64,
+    props: &RegisterProperties,
+) -> anyhow::Result<Node> {
+    let raddr = baseaddr + r.address_offset as u64;
+    let props = r.properties.derive_from(props);
+    let read_action = ra.get(&format!("{}.{}", derpath, r.name)).copied();
+    let rdesc = rm_white(r.description.as_deref().unwrap_or(""));
+    let path = format!("{}.{}", rpath, r.name);
+    match r {
+        Register::Single(r) => {
+            let mut rnode = Node::new(Kind::Register, &r.name, path);
+            rnode.address = Some(raddr);
+            rnode.offset = r.address_offset;
+            rnode.description = rdesc;
+            rnode.derived_from = r.derived_from.clone();
+            rnode.properties = props;
+            rnode.read_action = read_action;
+            add_fields_tree(&mut rnode, ev_map, ra, r, derpath, raddr)?;
+            Ok(rnode)
+        }
+        Register::Array(r, dim) => {
+            let mut anode = Node::new(Kind::RegisterArray, &r.name, path);
+            anode.offset = r.address_offset;
+            anode.description = rdesc.clone();
+            anode.derived_from = r.derived_from.clone();
+            anode.properties = props;
+            anode.read_action = read_action;
+            for (i, idx) in dim.indexes().enumerate() {
+                let offset = dim.dim_increment * (i as u32);
+                let rname = r.name.replace("[%s]", &idx).replace("%s", &idx);
+                let mut rnode = Node::new(Kind::Register, &rname, format!("{}.{}", rpath, rname));
+                rnode.address = Some(raddr + offset as u64);
+                rnode.offset = r.address_offset + offset;
+                rnode.description = rdesc.replace("%s", &idx);
+                rnode.derived_from = r.derived_from.clone();
+                rnode.properties = props;
+                rnode.read_action = read_action;
+                add_fields_tree(&mut rnode, ev_map, ra, r, derpath, raddr + offset as u64)?;
+                anode.children.push(rnode);
+            }
+            Ok(anode)
+        }
+    }
+}
+
+fn add_fields_tree(
+    rnode: &mut Node,
+    ev_map: &EvMap,
+    ra: &ReadActions,
+    r: &RegisterInfo,
+    derpath: &str,
+    raddr: u64,
+) -> anyhow::Result<()> {
+    if let Some(fields) = &r.fields {
+        for f in fields {
+            let mut enum_values = Vec::new();
+            for evalues in &f.enumerated_values {
+                let de = ev_map.resolve(
+                    evalues,
+                    &format!("{}.{}.{}", derpath, r.name, f.name),
+                    &format!("{}.{}", rnode.path, f.name),
+                )?;
+                enum_values.push(EnumValues {
+                    name: de.name.clone(),
+                    derived_from: evalues.derived_from.clone(),
+                    values: de
+                        .values
+                        .iter()
+                        .map(|ev| EnumValue {
+                            name: ev.name.clone(),
+                            description: rm_white(ev.description.as_deref().unwrap_or("")),
+                            value: ev.value,
+                        })
+                        .collect(),
+                });
+            }
+
+            let fdesc = rm_white(f.description.as_deref().unwrap_or(""));
+            let br = f.bit_range;
+            let read_action = ra
+                .get(&format!("{}.{}.{}", derpath, r.name, f.name))
+                .copied()
+                .or(rnode.read_action);
+
+            match f {
+                Field::Single(f) => {
+                    let mut fnode =
+                        Node::new(Kind::Field, &f.name, format!("{}.{}", rnode.path, f.name));
+                    fnode.address = Some(raddr);
+                    fnode.description = fdesc;
+                    fnode.bits = Some((br.offset, br.width));
+                    fnode.properties =
+                        field_properties(&rnode.properties, f.access, br.offset, br.width);
+                    fnode.derived_from = f.derived_from.clone();
+                    fnode.read_action = read_action;
+                    fnode.modified_write_values = f.modified_write_values;
+                    fnode.enum_values = enum_values;
+                    rnode.children.push(fnode);
+                }
+                Field::Array(f, dim) => {
+                    let mut anode = Node::new(
+                        Kind::FieldArray,
+                        &f.name,
+                        format!("{}.{}", rnode.path, f.name),
+                    );
+                    anode.description = fdesc.clone();
+                    anode.derived_from = f.derived_from.clone();
+                    anode.read_action = read_action;
+                    anode.modified_write_values = f.modified_write_values;
+                    anode.properties =
+                        field_properties(&rnode.properties, f.access, br.offset, br.width);
+                    for (i, idx) in dim.indexes().enumerate() {
+                        let offset = br.offset + dim.dim_increment * (i as u32);
+                        let fname = f.name.replace("[%s]", &idx).replace("%s", &idx);
+                        let mut fnode =
+                            Node::new(Kind::Field, &fname, format!("{}.{}", rnode.path, fname));
+                        fnode.address = Some(raddr);
+                        fnode.description = fdesc.replace("%s", &idx);
+                        fnode.bits = Some((offset, br.width));
+                        fnode.properties =
+                            field_properties(&rnode.properties, f.access, offset, br.width);
+                        fnode.derived_from = f.derived_from.clone();
+                        fnode.read_action = read_action;
+                        fnode.modified_write_values = f.modified_write_values;
+                        fnode.enum_values = enum_values.clone();
+                        anode.children.push(fnode);
+                    }
+                    rnode.children.push(anode);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Field has size of register, its own access or access of register
+/// and part of register reset value
+fn field_properties(
+    rprops: &RegisterProperties,
+    access: Option<Access>,
+    offset: u32,
+    width: u32,
+) -> RegisterProperties {
+    let mask = u64::MAX >> (64 - width.clamp(1, 64));
+    let mut props = RegisterProperties::new();
+    props.size = rprops.size;
+    props.access = access.or(rprops.access);
+    props.reset_value = rprops.reset_value.map(|v| (v >> offset) & mask);
+    props.reset_mask = rprops.reset_mask.map(|v| (v >> offset) & mask);
+    props
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(peripherals: &str) -> anyhow::Result<Device> {
+        Device::parse(&format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<device>
+  <name>TEST</name>
+  <addressUnitBits>8</addressUnitBits>
+  <width>32</width>
+  <size>32</size>
+  <peripherals>{}</peripherals>
+</device>"#,
+            peripherals
+        ))
+    }
+
+    fn peripheral(name: &str, base: u32, registers: &str) -> String {
+        format!(
+            r#"<peripheral>
+      <name>{}</name>
+      <baseAddress>{:#x}</baseAddress>
+      <addressBlock><offset>0</offset><size>0x400</size><usage>registers</usage></addressBlock>
+      <registers>{}</registers>
+    </peripheral>"#,
+            name, base, registers
+        )
+    }
+
+    fn device(registers: &str) -> Device {
+        parse(&peripheral("P", 0x4000_0000, registers)).unwrap()
+    }
+
+    fn node<'a>(device: &'a Device, path: &str) -> &'a Node {
+        device
+            .find(path)
+            .unwrap_or_else(|| panic!("{} not found", path))
+    }
+
+    #[test]
+    fn cluster_array() {
+        let d = device(
+            r#"<cluster>
+                 <dim>2</dim><dimIncrement>0x10</dimIncrement>
+                 <name>CH[%s]</name><description>Channel %s</description>
+                 <addressOffset>0x100</addressOffset>
+                 <register><name>CR</name><addressOffset>0x4</addressOffset></register>
+               </cluster>"#,
+        );
+        let array = node(&d, "P.CH[%s]");
+        assert_eq!(array.kind, Kind::ClusterArray);
+        assert_eq!(array.address, None);
+        let paths: Vec<&str> = array.children.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["P.CH0", "P.CH1"]);
+        assert_eq!(node(&d, "P.CH1").address, Some(0x4000_0110));
+        assert_eq!(node(&d, "P.CH1").description, "Channel 1");
+        let cr = node(&d, "P.CH1.CR");
+        assert_eq!(cr.kind, Kind::Register);
+        assert_eq!(cr.address, Some(0x4000_0114));
+        assert_eq!(node(&d, "P.CH0.CR").address, Some(0x4000_0104));
+    }
+
+    #[test]
+    fn register_array() {
+        let d = device(
+            r#"<register>
+                 <dim>3</dim><dimIncrement>4</dimIncrement><dimIndex>A,B,C</dimIndex>
+                 <name>DR%s</name><addressOffset>0x20</addressOffset><size>16</size>
+               </register>"#,
+        );
+        let array = node(&d, "P.DR%s");
+        assert_eq!(array.kind, Kind::RegisterArray);
+        let registers: Vec<(&str, Option<u64>)> = array
+            .children
+            .iter()
+            .map(|r| (r.path.as_str(), r.address))
+            .collect();
+        assert_eq!(
+            registers,
+            [
+                ("P.DRA", Some(0x4000_0020)),
+                ("P.DRB", Some(0x4000_0024)),
+                ("P.DRC", Some(0x4000_0028)),
+            ]
+        );
+        assert_eq!(node(&d, "P.DRC").properties.size, Some(16));
+        assert_eq!(
+            node(&d, "P.DRB").address_range(),
+            Some(0x4000_0024..0x4000_0026)
+        );
+    }
+
+    #[test]
+    fn field_array_offsets() {
+        let d = device(
+            r#"<register>
+                 <name>FR</name><addressOffset>0x30</addressOffset>
+                 <resetValue>0x5500</resetValue>
+                 <fields>
+                   <field>
+                     <dim>4</dim><dimIncrement>2</dimIncrement>
+                     <name>EN%s</name><bitOffset>8</bitOffset><bitWidth>1</bitWidth>
+                   </field>
+                 </fields>
+               </register>"#,
+        );
+        let array = node(&d, "P.FR.EN%s");
+        assert_eq!(array.kind, Kind::FieldArray);
+        let fields: Vec<(&str, Option<(u32, u32)>)> = array
+            .children
+            .iter()
+            .map(|f| (f.name.as_str(), f.bits))
+            .collect();
+        // Offsets start from `bitOffset` of array
+        assert_eq!(
+            fields,
+            [
+                ("EN0", Some((8, 1))),
+                ("EN1", Some((10, 1))),
+                ("EN2", Some((12, 1))),
+                ("EN3", Some((14, 1))),
+            ]
+        );
+        assert_eq!(node(&d, "P.FR.EN3").address, Some(0x4000_0030));
+        assert_eq!(node(&d, "P.FR.EN3").properties.reset_value, Some(1));
+        assert_eq!(node(&d, "P.FR.EN2").properties.reset_value, Some(1));
+    }
+
+    fn register(name: &str, offset: u32, fields: &[String]) -> String {
+        format!(
+            "<register><name>{}</name><addressOffset>{:#x}</addressOffset><fields>{}</fields></register>",
+            name,
+            offset,
+            fields.concat()
+        )
+    }
+
+    fn field(name: &str, offset: u32, enum_values: &str) -> String {
+        format!(
+            "<field><name>{}</name><bitOffset>{}</bitOffset><bitWidth>2</bitWidth>{}</field>",
+            name, offset, enum_values
+        )
+    }
+
+    /// `enumeratedValues` with `name` and values named `prefix0`, `prefix1`
+    fn values(name: &str, prefix: &str) -> String {
+        format!(
+            "<enumeratedValues><name>{n}</name>\
+               <enumeratedValue><name>{p}0</name><value>0</value></enumeratedValue>\
+               <enumeratedValue><name>{p}1</name><value>1</value></enumeratedValue>\
+             </enumeratedValues>",
+            n = name,
+            p = prefix
+        )
+    }
+
+    fn derived(derived_from: &str) -> String {
+        format!("<enumeratedValues derivedFrom=\"{}\"/>", derived_from)
+    }
+
+    fn value_names(device: &Device, path: &str) -> Vec<String> {
+        node(device, path)
+            .enum_values
+            .iter()
+            .flat_map(|evs| &evs.values)
+            .map(|ev| ev.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn enum_values_in_register_scope() {
+        let d = device(&register(
+            "CR",
+            0,
+            &[
+                field("A", 0, &values("MODE", "M")),
+                field("B", 2, &derived("MODE")),
+                field("C", 4, &derived("A.MODE")),
+            ],
+        ));
+        assert_eq!(value_names(&d, "P.CR.B"), ["M0", "M1"]);
+        assert_eq!(value_names(&d, "P.CR.C"), ["M0", "M1"]);
+        assert_eq!(
+            node(&d, "P.CR.B").enum_values[0].derived_from.as_deref(),
+            Some("MODE")
+        );
+    }
+
+    #[test]
+    fn enum_values_inner_scope_first() {
+        let d = device(
+            &[
+                register("CR1", 0, &[field("A", 0, &values("MODE", "X"))]),
+                register(
+                    "CR2",
+                    4,
+                    &[
+                        field("A", 0, &values("MODE", "Y")),
+                        field("B", 2, &derived("MODE")),
+                        field("C", 4, &derived("CR1.A.MODE")),
+                    ],
+                ),
+            ]
+            .concat(),
+        );
+        assert_eq!(value_names(&d, "P.CR2.B"), ["Y0", "Y1"]);
+        assert_eq!(value_names(&d, "P.CR2.C"), ["X0", "X1"]);
+    }
+
+    #[test]
+    fn enum_values_in_cluster_array() {
+        let d = device(&format!(
+            "<cluster><dim>2</dim><dimIncrement>0x10</dimIncrement>\
+               <name>CH[%s]</name><description>Channel</description>\
+               <addressOffset>0x100</addressOffset>{}{}</cluster>",
+            register("R1", 0, &[field("F", 0, &values("VALS", "V"))]),
+            register("R2", 4, &[field("G", 0, &derived("R1.F.VALS"))]),
+        ));
+        assert_eq!(value_names(&d, "P.CH0.R2.G"), ["V0", "V1"]);
+        assert_eq!(value_names(&d, "P.CH1.R2.G"), ["V0", "V1"]);
+    }
+
+    #[test]
+    fn enum_values_absolute_path() {
+        let d = parse(
+            &[
+                peripheral(
+                    "P",
+                    0x4000_0000,
+                    &register("CR", 0, &[field("A", 0, &values("MODE", "M"))]),
+                ),
+                peripheral(
+                    "Q",
+                    0x4000_1000,
+                    &register("SR", 0, &[field("B", 0, &derived("P.CR.A.MODE"))]),
+                ),
+            ]
+            .concat(),
+        )
+        .unwrap();
+        assert_eq!(value_names(&d, "Q.SR.B"), ["M0", "M1"]);
+    }
+
+    #[test]
+    fn enum_values_not_found() {
+        let e = parse(&peripheral(
+            "P",
+            0x4000_0000,
+            &register(
+                "CR",
+                0,
+                &[
+                    field("A", 0, &values("MODE", "M")),
+                    field("B", 2, &derived("NOPE")),
+                ],
+            ),
+        ))
+        .unwrap_err();
+        assert_eq!(
+            format!("{:#}", e),
+            "Field P.CR.B: enumeratedValues NOPE not found"
+        );
+    }
+
+    #[test]
+    fn enum_values_derive_loop() {
+        let looped = |name: &str, base: &str| {
+            format!(
+                "<enumeratedValues derivedFrom=\"{}\"><name>{}</name></enumeratedValues>",
+                base, name
+            )
+        };
+        let e = parse(&peripheral(
+            "P",
+            0x4000_0000,
+            &register(
+                "CR",
+                0,
+                &[
+                    field("A", 0, &looped("X", "Y")),
+                    field("B", 2, &looped("Y", "X")),
+                ],
+            ),
+        ))
+        .unwrap_err();
+        assert!(
+            format!("{:#}", e).contains("Field P.CR.A: derivedFrom loop in enumeratedValues"),
+            "{:#}",
+            e
+        );
+    }
+
+    fn read_action(device: &Device, path: &str) -> Option<ReadAction> {
+        node(device, path).read_action
+    }
+
+    #[test]
+    fn read_action_of_derived_register() {
+        let d = device(
+            r#"<register>
+                 <name>SR</name><addressOffset>0</addressOffset><readAction>clear</readAction>
+                 <fields>
+                   <field><name>A</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth></field>
+                   <field>
+                     <name>B</name><bitOffset>1</bitOffset><bitWidth>1</bitWidth>
+                     <readAction>set</readAction>
+                   </field>
+                 </fields>
+               </register>
+               <register derivedFrom="SR"><name>SR2</name><addressOffset>4</addressOffset></register>"#,
+        );
+        assert_eq!(read_action(&d, "P.SR2"), Some(ReadAction::Clear));
+        assert_eq!(read_action(&d, "P.SR2.A"), Some(ReadAction::Clear));
+        assert_eq!(read_action(&d, "P.SR2.B"), Some(ReadAction::Set));
+    }
+
+    #[test]
+    fn read_action_of_derived_cluster() {
+        let d = device(
+            r#"<cluster>
+                 <name>CH1</name><description>Channel</description>
+                 <addressOffset>0</addressOffset>
+                 <register>
+                   <name>SR</name><addressOffset>0</addressOffset><readAction>clear</readAction>
+                 </register>
+               </cluster>
+               <cluster derivedFrom="CH1"><name>CH2</name><addressOffset>0x10</addressOffset></cluster>"#,
+        );
+        assert_eq!(read_action(&d, "P.CH2.SR"), Some(ReadAction::Clear));
+    }
+
+    #[test]
+    fn read_action_of_derived_field() {
+        let d = device(
+            r#"<register>
+                 <name>SR</name><addressOffset>0</addressOffset>
+                 <fields>
+                   <field>
+                     <name>A</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth>
+                     <readAction>modify</readAction>
+                   </field>
+                   <field derivedFrom="A"><name>B</name><bitOffset>1</bitOffset><bitWidth>1</bitWidth></field>
+                 </fields>
+               </register>
+               <register>
+                 <name>CR</name><addressOffset>4</addressOffset>
+                 <fields>
+                   <field derivedFrom="SR.A"><name>C</name><bitOffset>2</bitOffset><bitWidth>1</bitWidth></field>
+                 </fields>
+               </register>"#,
+        );
+        assert_eq!(read_action(&d, "P.SR"), None);
+        assert_eq!(read_action(&d, "P.SR.B"), Some(ReadAction::Modify));
+        assert_eq!(read_action(&d, "P.CR.C"), Some(ReadAction::Modify));
+    }
+
+    #[test]
+    fn read_action_of_overriding_register() {
+        let registers = r#"
+            <register><name>SR</name><addressOffset>0</addressOffset><readAction>clear</readAction></register>
+            <register><name>DR</name><addressOffset>4</addressOffset><readAction>clear</readAction></register>"#;
+        let d = parse(&format!(
+            r#"{}
+            <peripheral derivedFrom="P">
+              <name>Q</name><baseAddress>0x40001000</baseAddress>
+              <registers>
+                <register><name>DR</name><addressOffset>4</addressOffset></register>
+              </registers>
+            </peripheral>"#,
+            peripheral("P", 0x4000_0000, registers)
+        ))
+        .unwrap();
+        assert_eq!(read_action(&d, "Q.SR"), Some(ReadAction::Clear));
+        assert_eq!(read_action(&d, "Q.DR"), None);
+        assert_eq!(read_action(&d, "P.DR"), Some(ReadAction::Clear));
+    }
+
+    fn described_register(name: &str, offset: u32, description: &str) -> String {
+        format!(
+            "<register><name>{}</name><description>{}</description>\
+               <addressOffset>{:#x}</addressOffset></register>",
+            name, description, offset
+        )
+    }
+
+    fn derived_register(name: &str, offset: u32, derived_from: &str) -> String {
+        format!(
+            "<register derivedFrom=\"{}\"><name>{}</name><addressOffset>{:#x}</addressOffset></register>",
+            derived_from, name, offset
+        )
+    }
+
+    #[test]
+    fn derived_register_lookup_order() {
+        let d = parse(
+            &[
+                peripheral(
+                    "P",
+                    0x4000_0000,
+                    &format!(
+                        "{}{}<cluster><name>C</name><description>Cluster</description>\
+                           <addressOffset>0x100</addressOffset>{}{}{}{}</cluster>",
+                        described_register("R", 0, "Outer"),
+                        described_register("S", 4, "Peripheral"),
+                        described_register("R", 0, "Inner"),
+                        derived_register("RELATIVE", 4, "R"),
+                        derived_register("OUTER", 8, "S"),
+                        derived_register("ABSOLUTE", 0xc, "Q.T"),
+                    ),
+                ),
+                peripheral("Q", 0x4000_1000, &described_register("T", 0, "Other")),
+            ]
+            .concat(),
+        )
+        .unwrap();
+        // Current cluster first, then outer scopes, then absolute path
+        assert_eq!(node(&d, "P.C.RELATIVE").description, "Inner");
+        assert_eq!(node(&d, "P.C.OUTER").description, "Peripheral");
+        assert_eq!(node(&d, "P.C.ABSOLUTE").description, "Other");
+    }
+
+    #[test]
+    fn derived_cluster_children_in_base_scope() {
+        let d = device(&format!(
+            "{}<cluster><name>C1</name><description>Cluster</description>\
+               <addressOffset>0x100</addressOffset>{}{}</cluster>\
+             <cluster derivedFrom=\"C1\"><name>C2</name><addressOffset>0x200</addressOffset></cluster>",
+            described_register("A", 0, "Peripheral"),
+            described_register("A", 0, "Cluster"),
+            derived_register("B", 4, "A"),
+        ));
+        assert_eq!(node(&d, "P.C2.A").address, Some(0x4000_0200));
+        // `B` of `C2` refers to `A` of `C1`, not to `A` of peripheral
+        assert_eq!(node(&d, "P.C2.B").description, "Cluster");
+    }
+
+    #[test]
+    fn derived_field_of_other_register() {
+        let d = device(
+            r#"<register>
+                 <name>CR</name><addressOffset>0</addressOffset>
+                 <fields>
+                   <field>
+                     <name>EN</name><description>Enable</description>
+                     <bitOffset>0</bitOffset><bitWidth>1</bitWidth>
+                   </field>
+                 </fields>
+               </register>
+               <register>
+                 <name>CR2</name><addressOffset>4</addressOffset>
+                 <fields>
+                   <field derivedFrom="CR.EN"><name>EN2</name><bitOffset>3</bitOffset><bitWidth>1</bitWidth></field>
+                 </fields>
+               </register>"#,
+        );
+        let en2 = node(&d, "P.CR2.EN2");
+        assert_eq!(en2.description, "Enable");
+        assert_eq!(en2.bits, Some((3, 1)));
+    }
+
+    #[test]
+    fn derived_register_keeps_own_dimension() {
+        let d = device(
+            r#"<register>
+                 <dim>4</dim><dimIncrement>4</dimIncrement>
+                 <name>A%s</name><description>Base</description><addressOffset>0</addressOffset>
+               </register>
+               <register derivedFrom="A%s">
+                 <dim>2</dim><dimIncrement>8</dimIncrement>
+                 <name>B%s</name><addressOffset>0x20</addressOffset>
+               </register>"#,
+        );
+        let array = node(&d, "P.B%s");
+        let registers: Vec<(&str, Option<u64>)> = array
+            .children
+            .iter()
+            .map(|r| (r.path.as_str(), r.address))
+            .collect();
+        assert_eq!(
+            registers,
+            [("P.B0", Some(0x4000_0020)), ("P.B1", Some(0x4000_0028))]
+        );
+        assert_eq!(node(&d, "P.B1").description, "Base");
+    }
+
+    #[test]
+    fn derived_from_different_type() {
+        let e = parse(&peripheral(
+            "P",
+            0x4000_0000,
+            &format!(
+                "{}<cluster derivedFrom=\"R\"><name>C</name><addressOffset>0x10</addressOffset></cluster>",
+                described_register("R", 0, "Register")
+            ),
+        ))
+        .unwrap_err();
+        assert!(
+            format!("{:#}", e).contains("P.C: derivedFrom R has different type"),
+            "{:#}",
+            e
+        );
+    }
+
+    #[test]
+    fn derived_register_loop() {
+        let e = parse(&peripheral(
+            "P",
+            0x4000_0000,
+            &[derived_register("A", 0, "B"), derived_register("B", 4, "A")].concat(),
+        ))
+        .unwrap_err();
+        assert!(format!("{:#}", e).ends_with("derivedFrom loop"), "{:#}", e);
+    }
+
+    #[test]
+    fn derived_peripheral_registers() {
+        let d = parse(&format!(
+            r#"{}
+            <peripheral derivedFrom="P">
+              <name>Q</name><baseAddress>0x40001000</baseAddress>
+              <registers>
+                <register><name>DR</name><addressOffset>8</addressOffset><size>16</size></register>
+                <register><name>BRR</name><addressOffset>0xc</addressOffset></register>
+              </registers>
+            </peripheral>"#,
+            peripheral(
+                "P",
+                0x4000_0000,
+                &[
+                    described_register("SR", 0, "Status"),
+                    described_register("DR", 4, "Data"),
+                ]
+                .concat()
+            )
+        ))
+        .unwrap();
+        let names: Vec<&str> = node(&d, "Q")
+            .children
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect();
+        // Overriding register stays at place of base one, new ones are appended
+        assert_eq!(names, ["SR", "DR", "BRR"]);
+        assert_eq!(node(&d, "Q.SR").address, Some(0x4000_1000));
+        let dr = node(&d, "Q.DR");
+        assert_eq!(dr.address, Some(0x4000_1008));
+        assert_eq!(dr.properties.size, Some(16));
+        assert_eq!(dr.description, "");
+        assert_eq!(node(&d, "P.DR").address, Some(0x4000_0004));
+        // Address block is inherited
+        match d.lookup(0x4000_1100) {
+            Some(Lookup::Block { peripheral, block }) => {
+                assert_eq!(peripheral.name, "Q");
+                assert_eq!(block, 0x4000_1000..0x4000_1400);
+            }
+            _ => panic!("address block of Q not inherited"),
+        }
+    }
+
+    #[test]
+    fn derived_peripheral_not_found() {
+        let e = parse(
+            r#"<peripheral derivedFrom="NOPE"><name>Q</name><baseAddress>0x40001000</baseAddress></peripheral>"#,
+        )
+        .unwrap_err();
+        assert!(
+            format!("{:#}", e).ends_with("Q: derivedFrom NOPE not found"),
+            "{:#}",
+            e
+        );
+    }
+
+    #[test]
+    fn lookup_address() {
+        let d = device(
+            r#"<register>
+                 <name>DR</name><addressOffset>0x10</addressOffset><size>16</size>
+                 <fields>
+                   <field><name>LOW</name><bitOffset>0</bitOffset><bitWidth>4</bitWidth></field>
+                   <field><name>MID</name><bitOffset>6</bitOffset><bitWidth>4</bitWidth></field>
+                   <field><name>HIGH</name><bitOffset>12</bitOffset><bitWidth>4</bitWidth></field>
+                 </fields>
+               </register>
+               <register><name>BR</name><addressOffset>0x20</addressOffset><size>8</size></register>"#,
+        );
+        let register = |address| match d.lookup(address) {
+            Some(Lookup::Register { register, fields }) => {
+                let fields: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+                Some((register.path.clone(), fields))
+            }
+            _ => None,
+        };
+        // Fields are those with bits in addressed byte
+        assert_eq!(
+            register(0x4000_0010),
+            Some(("P.DR".to_string(), vec!["LOW", "MID"]))
+        );
+        assert_eq!(
+            register(0x4000_0011),
+            Some(("P.DR".to_string(), vec!["MID", "HIGH"]))
+        );
+        assert_eq!(register(0x4000_0012), None);
+        assert_eq!(register(0x4000_0020), Some(("P.BR".to_string(), vec![])));
+
+        // Gap after 8-bit register is still inside address block of peripheral
+        match d.lookup(0x4000_0021) {
+            Some(Lookup::Block { peripheral, block }) => {
+                assert_eq!(peripheral.name, "P");
+                assert_eq!(block, 0x4000_0000..0x4000_0400);
+            }
+            _ => panic!("0x40000021 not in address block"),
+        }
+        assert!(d.lookup(0x4000_0400).is_none());
+        assert!(d.lookup(0x3fff_ffff).is_none());
+    }
+}
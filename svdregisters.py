@@ -1,27 +1,108 @@
 import os.path
-import struct
+import shlex
+
+V2_HEADER = "# svdregisters v2"
+
+def quote(s):
+    if s and not any(c in s for c in ' \t"\'\\#'):
+        return s
+    return '"' + s.replace('\\', '\\\\').replace('"', '\\"') + '"'
+
+C_TYPES = {1: "unsigned char", 2: "unsigned short", 4: "unsigned int", 8: "unsigned long long"}
+
+def c_type(size):
+    return C_TYPES.get((size + 7) // 8, "unsigned int")
+
+def read_registers_file(filename):
+    """Returns format version, SVD file name with path inside archive (or None)
+    and entries split in values and attributes"""
+    with open(filename, 'r') as f:
+        lines = [l.strip() for l in f.readlines()]
+    lines = [l for l in lines if l]
+    if lines and lines[0] == V2_HEADER:
+        version, split, no_alias = 2, shlex.split, ""
+        lines = lines[1:]
+        svd = (split(lines[0]) + [None])[:2] if lines else ["", None]
+    else:
+        version, split, no_alias = 1, str.split, "_"
+        svd = [lines[0] if lines else "", None]
+    entries = []
+    for l in lines[1:]:
+        if l.startswith('#'):
+            continue
+        # Lines which can't be parsed are skipped, svdselector reports them
+        try:
+            values = split(l)
+            n = next((i for i in range(3, len(values)) if '=' in values[i]), len(values))
+            values, attrs = values[:n], dict(a.split('=', 1) for a in values[n:])
+            int(attrs.get("size", 32))
+            [int(v, 0) for v in values[2:]]
+        except ValueError:
+            continue
+        if len(values) in (3, 5):
+            if values[1] == no_alias:
+                values[1] = ""
+            entries.append((values, attrs))
+    return version, tuple(svd), entries
+
+def svd_parser(svd, inner=None):
+    """SVDParser for plain, .gz or .xz SVD file, or for file `inner` of zip or CMSIS-Pack"""
+    from cmsis_svd.parser import SVDParser
+    if inner is not None:
+        import zipfile
+        with zipfile.ZipFile(svd) as z:
+            data = z.read(inner)
+    elif svd.endswith('.gz'):
+        import gzip
+        with gzip.open(svd) as f:
+            data = f.read()
+    elif svd.endswith('.xz'):
+        import lzma
+        with lzma.open(svd) as f:
+            data = f.read()
+    else:
+        return SVDParser.for_xml_file(svd)
+    import xml.etree.ElementTree as ET
+    return SVDParser(ET.ElementTree(ET.fromstring(data)))
 
 class Register:
-    def __init__ (self, name, alias, address):
-        self.name, self.address = name, address
-        self.alias = name if alias=="_" else alias
+    def __init__ (self, name, alias, address, size=32):
+        self.name, self.address, self.size = name, address, size
+        self.alias = alias or name
+        self.read_action = None
         self.changed = False
     
     @staticmethod
-    def from_str(s):
-        name, alias, address = s.split()
-        return Register(name, alias, address)
+    def from_values(values, attrs):
+        name, alias, address = values
+        r = Register(name, alias, address, int(attrs.get("size", 32)))
+        r.read_action = attrs.get("read")
+        return r
     
-    def __str__ (self):
-        alias = "_" if self.alias == self.name else self.alias
-        return "{} {} {}\n".format(self.name, alias, self.address)
+    def to_line (self, version):
+        if version == 1:
+            alias = "_" if self.alias == self.name else self.alias
+            line = "{} {} {}".format(self.name, alias, self.address)
+        else:
+            alias = "" if self.alias == self.name else self.alias
+            line = "{} {} {}".format(quote(self.name), quote(alias), self.address)
+        return line + self.bits() + self.attrs()
+    
+    def bits (self):
+        return ""
+    
+    def attrs (self):
+        attrs = " size={}".format(self.size) if self.size != 32 else ""
+        if self.read_action:
+            attrs += " read={}".format(self.read_action)
+        return attrs
     
     @property
     def gdbvalue(self):
         inferior = gdb.selected_inferior()
-        memory = inferior.read_memory(int(self.address, 0), 4)
-        m = struct.unpack("<L", memory)[0]
-        return gdb.parse_and_eval(str(m))
+        memory = inferior.read_memory(int(self.address, 0), (self.size + 7) // 8)
+        m = int.from_bytes(bytes(memory), "little")
+        return gdb.parse_and_eval("({}){}".format(c_type(self.size), m))
     
     def format_value (self, FORMAT):
         value = self.gdbvalue
@@ -48,41 +129,45 @@ class Register:
         if oldvalue.type.code == gdb.TYPE_CODE_INT:
             width = oldvalue.type.sizeof * 8
             if 0 <= value < (2 ** width):
-                run("set *{0} = {1}".format(self.address, value))
+                run("set *({0} *){1} = {2}".format(c_type(self.size), self.address, value))
 
     @staticmethod
     def find_recursive(rs, name, path, baseaddr):
         for r in rs:
             if r.name == path[0]:
                 raddr = format_address(baseaddr + r.address_offset)
+                size = getattr(r, "size", None) or 32
                 if len(path) == 1:
-                    return Register(name, name, raddr)
+                    return Register(name, name, raddr, size)
                 else:
                     for f in r.fields:
                         if f.name == path[1]:
-                            return Field(name, name, raddr, f.bit_offset, f.bit_width)
+                            return Field(name, name, raddr, f.bit_offset, f.bit_width, size)
 
 class Field (Register):
-    def __init__ (self, name, alias, address, boffset, bwidth):
+    def __init__ (self, name, alias, address, boffset, bwidth, size=32):
         self.name, self.address, self.boffset, self.bwidth = name, address, boffset, bwidth
-        self.alias = name if alias=="_" else alias
+        self.size = size
+        self.alias = alias or name
+        self.read_action = None
         self.changed = False
     
     @staticmethod
-    def from_str(s):
-        name, alias, address, boffset, bwidth = s.split()
-        return Field(name, alias, address, int(boffset), int(bwidth))
+    def from_values(values, attrs):
+        name, alias, address, boffset, bwidth = values
+        f = Field(name, alias, address, int(boffset), int(bwidth), int(attrs.get("size", 32)))
+        f.read_action = attrs.get("read")
+        return f
     
-    def __str__ (self):
-        alias = "_" if self.alias == self.name else self.alias
-        return "{} {} {} {} {}".format(self.name, alias, self.address, self.boffset, self.bwidth)
+    def bits (self):
+        return " {} {}".format(self.boffset, self.bwidth)
     
     def format_value (self, FORMAT):
         value = self.gdbvalue
         try:
             if value.type.code in [gdb.TYPE_CODE_INT, gdb.TYPE_CODE_PTR]:
                 int_value = to_unsigned(value, value.type.sizeof)
-                int_value = (int_value >> self.boffset) & (0xffff_ffff >> (32 - self.bwidth))
+                int_value = (int_value >> self.boffset) & ((1 << self.bwidth) - 1)
                 if FORMAT == "BIN":
                     value_format = '0b{{:0{}b}}'.format(self.bwidth)
                 elif FORMAT == "DECIMAL":
@@ -99,9 +184,9 @@ class Field (Register):
         if oldvalue.type.code == gdb.TYPE_CODE_INT:
             int_value = to_unsigned(oldvalue, oldvalue.type.sizeof)
             if 0 <= value < (2 ** self.bwidth):
-                clean_mask = (0xffff_ffff >> (32 - self.bwidth))
-                newvalue = oldvalue & ~(clean_mask << self.boffset) | (value << self.boffset)
-                run("set *{0} = {1}".format(self.address, newvalue))
+                clean_mask = (1 << self.bwidth) - 1
+                newvalue = int_value & ~(clean_mask << self.boffset) | (value << self.boffset)
+                run("set *({0} *){1} = {2}".format(c_type(self.size), self.address, newvalue))
 
 
 class SvdRegisters (Dashboard.Module):
@@ -125,19 +210,20 @@ class SvdRegisters (Dashboard.Module):
         out = []
         registers = []
         if os.path.isfile(SvdRegisters.FILE):
-            with open(SvdRegisters.FILE, 'r') as f:
-                lines = [l.strip() for l in f.readlines()]
-                lines = [l for l in lines if l]
+            _, _, entries = read_registers_file(SvdRegisters.FILE)
             
             changed_list = []
-            for reg_info in lines[1:]:
+            for values, attrs in entries:
                 # fetch register and update the table
-                reg_split = reg_info.split()
-                if len(reg_split) == 3:
-                    r = Register.from_str(reg_info)
-                elif len(reg_split) == 5:
-                    r = Field.from_str(reg_info)
-                r.value = r.format_value(self.FORMAT)
+                if len(values) == 3:
+                    r = Register.from_values(values, attrs)
+                else:
+                    r = Field.from_values(values, attrs)
+                if r.read_action:
+                    # reading changes device state, so it is not read on every step
+                    r.value = "<read {}>".format(r.read_action)
+                else:
+                    r.value = r.format_value(self.FORMAT)
                 old_r = self.table.get(r.alias, None)
                 r.changed = old_r and (old_r.value != r.value) and not self.FORMAT_CHANGED
                 self.table[r.alias] = r
@@ -200,10 +286,9 @@ class SvdRegisters (Dashboard.Module):
                 raise Exception("Cannot import SVDParser. Check 'cmsis_svd' library installed")
             if os.path.isfile(SvdRegisters.FILE):
                 try:
-                    with open(SvdRegisters.FILE, 'r') as f:
-                        lines = [l.strip() for l in f.readlines()]
-                        parser = SVDParser.for_xml_file(lines[0])
-                        self.svd_device = parser.get_device()
+                    _, svd, _ = read_registers_file(SvdRegisters.FILE)
+                    parser = svd_parser(*svd)
+                    self.svd_device = parser.get_device()
                 except:
                     raise Exception("Cannot load or parse SVD file")
             else:
@@ -214,9 +299,10 @@ class SvdRegisters (Dashboard.Module):
             if name not in self.table:
                 r = self.find_register(name)
                 if r:
-                    r.alias = args[1] if len(args) > 1 else "_"
+                    r.alias = args[1] if len(args) > 1 else name
+                    version, _, _ = read_registers_file(SvdRegisters.FILE)
                     with open(SvdRegisters.FILE, "a") as f:
-                        f.write(str(r)+"\n")
+                        f.write(r.to_line(version)+"\n")
                 else:
                     raise Exception("Register {} not found".format(name))
             else:
@@ -236,11 +322,19 @@ class SvdRegisters (Dashboard.Module):
 
     def remove (self, arg):
         if os.path.isfile(SvdRegisters.FILE):
+            version, _, _ = read_registers_file(SvdRegisters.FILE)
+            split = shlex.split if version == 2 else str.split
             with open(SvdRegisters.FILE, 'r') as f:
                 lines = f.readlines()
-            newlines = [l for l in lines[1:] if arg not in l.split()[:2]]
+            head = lines[:version]
+            def keep(l):
+                try:
+                    return l.startswith('#') or arg not in split(l)[:2]
+                except ValueError:
+                    return True
+            newlines = [l for l in lines[version:] if keep(l)]
             with open(SvdRegisters.FILE, 'w') as f:
-                f.write(lines[0]+"".join(newlines))
+                f.write("".join(head+newlines))
         if arg in self.table:
             del self.table[arg]
 